@@ -0,0 +1,91 @@
+use std::io::Error;
+
+use gif::{Encoder, Frame, Repeat};
+
+use crate::{render::{self, Atlas, Draw, Style}, Field, Tile};
+
+/// Records the rounds of a generation as the frames of an animated GIF.
+pub struct Recorder<'a> {
+	style: Style,
+	atlas: Option<&'a Atlas>,
+	/// Delay between frames in hundredths of a second.
+	delay: u16,
+	/// Rounds left out between two frames.
+	skip: usize,
+	rounds: usize,
+	/// Whether the latest round was kept.
+	kept: bool,
+	encoder: Option<Encoder<Vec<u8>>>,
+	/// The first error encoding a frame, reported by `finish`.
+	error: Option<Error>,
+}
+impl<'a> Recorder<'a> {
+	/// A recorder keeping every `skip + 1`th round, shown for `delay_ms`
+	/// milliseconds each. GIFs count in hundredths, so the delay is rounded to
+	/// those.
+	pub fn new(style: Style, atlas: Option<&'a Atlas>, delay_ms: u64, skip: usize) -> Self {
+		Self {
+			style,
+			atlas,
+			delay: (delay_ms / 10).min(u16::MAX as u64) as u16,
+			skip,
+			rounds: 0,
+			kept: false,
+			encoder: None,
+			error: None,
+		}
+	}
+	/// Adds the field as a frame, unless the round is one to skip.
+	pub fn record<T: Tile + Draw>(&mut self, field: &Field<T>) {
+		self.kept = self.rounds.is_multiple_of(self.skip.saturating_add(1));
+		self.rounds += 1;
+		if self.kept {
+			self.frame(field);
+		}
+	}
+	fn frame<T: Tile + Draw>(&mut self, field: &Field<T>) {
+		if self.error.is_some() {
+			return;
+		}
+
+		let image = render::render(field, &self.style, self.atlas);
+		let (Ok(width), Ok(height)) = (u16::try_from(image.width), u16::try_from(image.height)) else {
+			self.error = Some(Error::other(format!("GIFs are at most {0}x{0} pixels", u16::MAX)));
+			return;
+		};
+
+		if self.encoder.is_none() {
+			let encoder = Encoder::new(vec![], width, height, &[])
+				.and_then(|mut encoder| encoder.set_repeat(Repeat::Infinite).map(|()| encoder));
+			match encoder {
+				Ok(encoder) => self.encoder = Some(encoder),
+				Err(e) => {
+					self.error = Some(Error::other(e));
+					return;
+				},
+			}
+		}
+
+		let mut pixels = image.pixels.as_flattened().to_vec();
+		let mut frame = Frame::from_rgba_speed(width, height, &mut pixels, 10);
+		frame.delay = self.delay;
+		if let Some(Err(e)) = self.encoder.as_mut().map(|encoder| encoder.write_frame(&frame)) {
+			self.error = Some(Error::other(e));
+		}
+	}
+	/// Ends the animation on the finished field, if its round was skipped, and
+	/// returns the GIF.
+	pub fn finish<T: Tile + Draw>(mut self, field: &Field<T>) -> Result<Vec<u8>, Error> {
+		if !self.kept {
+			self.frame(field);
+		}
+		if let Some(e) = self.error {
+			return Err(e);
+		}
+
+		match self.encoder {
+			Some(encoder) => encoder.into_inner().map_err(Error::other),
+			None => Err(Error::other("No frames were recorded")),
+		}
+	}
+}
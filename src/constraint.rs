@@ -0,0 +1,760 @@
+use std::{collections::{hash_map::Entry, HashMap}, fmt::Display};
+
+use crate::{state_set::StateSet, Direction, Domain, Field, Pipe, Tile};
+
+/// A rule about the whole field, on top of which tiles fit next to each other.
+pub trait Constraint<T: Tile> {
+	/// Looks at the field once it has been propagated, given the cells that
+	/// changed since the last look. Returns the tiles still allowed in some
+	/// cells, or why the field can no longer be completed.
+	fn check(&mut self, field: &Field<T>, changed: &[usize]) -> Result<Vec<(usize, StateSet)>, Violation>;
+	/// Forgets whatever was kept from earlier checks, before the first check
+	/// of a new field.
+	fn reset(&mut self) {}
+}
+
+/// Why a constraint can no longer be kept, and the cell where that came up.
+#[derive(Debug, Clone)]
+pub struct Violation {
+	pub index: usize,
+	pub reason: String,
+}
+impl Display for Violation {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(f, "{}", self.reason)
+	}
+}
+
+/// Which tiles have pipes towards which side.
+#[derive(Clone, Debug)]
+struct Pipes {
+	/// `connecting[direction]` holds the tiles with a pipe towards `direction`.
+	connecting: [StateSet; 6],
+	/// Tiles without any pipes.
+	empty: StateSet,
+}
+impl Pipes {
+	fn new<T: Pipe>(tiles: &[T]) -> Self {
+		let mut connecting = [StateSet::empty(); 6];
+		let mut empty = StateSet::empty();
+		for (index, tile) in tiles.iter().enumerate() {
+			for direction in Direction::all() {
+				if tile.connects(direction) {
+					connecting[direction as usize].insert(index);
+				}
+			}
+			if Direction::all().iter().all(|direction| !tile.connects(*direction)) {
+				empty.insert(index);
+			}
+		}
+
+		Self { connecting, empty }
+	}
+	fn must_hold_pipe<T: Tile>(&self, field: &Field<T>, index: usize) -> bool {
+		(field.domains[index].states() & self.empty).is_empty()
+	}
+}
+
+/// Cells grouped by which pipes could still join them.
+///
+/// The groups are kept from one check to the next. A pipe that can no longer
+/// be placed can only split a group, which is found by searching from both of
+/// its ends at once until the searches meet or one of them runs out of cells.
+/// Pipes that can be placed again, after a collapse was undone, join groups by
+/// moving the smaller one into the larger.
+struct Grouping {
+	pipes: Pipes,
+	/// The sides every cell may have pipes towards as `Direction` bits, as of
+	/// the last check.
+	sides: Vec<u8>,
+	/// Whether every cell must hold a pipe, as of the last check.
+	required: Vec<bool>,
+	/// Group of every cell that may hold a pipe.
+	groups: Vec<Option<usize>>,
+	/// Number of cells in every group.
+	sizes: HashMap<usize, usize>,
+	/// Number of cells that must hold pipes in every group with any.
+	requiring: HashMap<usize, usize>,
+	next_group: usize,
+	/// The search that last reached every cell, and from which end.
+	visits: Vec<(usize, usize)>,
+	searches: usize,
+}
+impl Grouping {
+	fn new(pipes: Pipes) -> Self {
+		Self {
+			pipes,
+			sides: vec![],
+			required: vec![],
+			groups: vec![],
+			sizes: HashMap::new(),
+			requiring: HashMap::new(),
+			next_group: 0,
+			visits: vec![],
+			searches: 0,
+		}
+	}
+	/// Makes the next update group every cell from scratch.
+	fn reset(&mut self) {
+		// the sides no longer match the field
+		self.sides.clear();
+	}
+	/// The neighbour in `direction` if a pipe may still join it to the cell.
+	fn joined<T: Tile>(&self, field: &Field<T>, index: usize, direction: Direction) -> Option<usize> {
+		let neighbour = field.neighbour(index, direction)?;
+
+		match has_side(self.sides[index], direction) && has_side(self.sides[neighbour], direction.opposite()) {
+			true => Some(neighbour),
+			false => None,
+		}
+	}
+	/// Moves the cell into `group`, or out of any with `None`.
+	fn move_cell(&mut self, index: usize, group: Option<usize>) {
+		if let Some(old) = std::mem::replace(&mut self.groups[index], group) {
+			uncount(&mut self.sizes, old);
+			if self.required[index] {
+				uncount(&mut self.requiring, old);
+			}
+		}
+		if let Some(group) = group {
+			*self.sizes.entry(group).or_default() += 1;
+			if self.required[index] {
+				*self.requiring.entry(group).or_default() += 1;
+			}
+		}
+	}
+	/// Groups every cell that may hold a pipe from scratch.
+	fn regroup<T: Tile>(&mut self, field: &Field<T>) {
+		self.groups = vec![None; field.domains.len()];
+		self.sizes.clear();
+		self.requiring.clear();
+
+		for start in 0..field.domains.len() {
+			if self.groups[start].is_some() || self.sides[start] == 0 {
+				continue;
+			}
+
+			let group = self.new_group();
+			self.move_cell(start, Some(group));
+			let mut stack = vec![start];
+			while let Some(index) = stack.pop() {
+				for direction in Direction::all() {
+					match self.joined(field, index, direction) {
+						Some(neighbour) if self.groups[neighbour].is_none() => {
+							self.move_cell(neighbour, Some(group));
+							stack.push(neighbour);
+						},
+						_ => {},
+					}
+				}
+			}
+		}
+	}
+	/// Searches from `a` and `b` at once, a cell at a time each, along pipes
+	/// that may still be placed. Returns every cell found from one of them if
+	/// that search ran out of cells before meeting the other.
+	fn search<T: Tile>(&mut self, field: &Field<T>, a: usize, b: usize) -> Option<Vec<usize>> {
+		self.searches += 1;
+		let search = self.searches;
+
+		let mut found = [vec![a], vec![b]];
+		let mut next = [0, 0];
+		self.visits[a] = (search, 0);
+		self.visits[b] = (search, 1);
+		loop {
+			for end in 0..2 {
+				let Some(&index) = found[end].get(next[end]) else {
+					return Some(std::mem::take(&mut found[end]));
+				};
+				next[end] += 1;
+
+				for direction in Direction::all() {
+					let Some(neighbour) = self.joined(field, index, direction) else {
+						continue;
+					};
+					match self.visits[neighbour] {
+						(visit, from) if visit == search && from != end => return None,
+						(visit, _) if visit == search => {},
+						_ => {
+							self.visits[neighbour] = (search, end);
+							found[end].push(neighbour);
+						},
+					}
+				}
+			}
+		}
+	}
+	/// The neighbour in `direction` if a pipe may join it to the cell, or could
+	/// before the cells in `before` changed from the sides held there.
+	fn linked<T: Tile>(&self, field: &Field<T>, before: &HashMap<usize, u8>, index: usize, direction: Direction) -> Option<usize> {
+		let neighbour = field.neighbour(index, direction)?;
+		let old = |index: usize| before.get(&index).copied().unwrap_or(self.sides[index]);
+
+		match has_side(old(index), direction) && has_side(old(neighbour), direction.opposite()) {
+			true => Some(neighbour),
+			false => self.joined(field, index, direction),
+		}
+	}
+	/// A group no cell has been in yet.
+	fn new_group(&mut self) -> usize {
+		self.next_group += 1;
+		self.next_group - 1
+	}
+	/// Takes in the cells that changed since the last check, or groups every
+	/// cell from scratch after a reset. Pipes that may be placed again join
+	/// groups, and the ones that no longer can split them. Returns the cells
+	/// that had pipes before and have none now, and the cells that moved to
+	/// another group or `None` if every cell was grouped from scratch.
+	fn update<T: Tile>(&mut self, field: &Field<T>, changed: &[usize]) -> (Vec<usize>, Option<Vec<usize>>) {
+		if self.sides.len() != field.domains.len() {
+			self.sides = vec![0; field.domains.len()];
+			self.required = vec![false; field.domains.len()];
+			self.visits = vec![(0, 0); field.domains.len()];
+			for index in 0..field.domains.len() {
+				(self.sides[index], self.required[index]) = self.look(field, index);
+			}
+			self.regroup(field);
+
+			// with tiles that have pipes, cells without any lost them before the first check
+			let emptied = match self.pipes.connecting.iter().any(|states| !states.is_empty()) {
+				true => (0..field.domains.len()).filter(|index| self.sides[*index] == 0).collect(),
+				false => vec![],
+			};
+			return (emptied, None);
+		}
+
+		// the sides of the cells that changed as they were before
+		let mut before = HashMap::new();
+		let mut order = vec![];
+		let mut moved = vec![];
+		for &index in changed {
+			let (sides, required) = self.look(field, index);
+			if sides == self.sides[index] && required == self.required[index] {
+				continue;
+			}
+
+			if let Entry::Vacant(entry) = before.entry(index) {
+				entry.insert(self.sides[index]);
+				order.push(index);
+			}
+			// cells stay in their group until the groups are joined, so it can
+			// still be found through them
+			let group = self.groups[index];
+			self.move_cell(index, None);
+			self.sides[index] = sides;
+			self.required[index] = required;
+			let group = match group {
+				None if sides == 0 => None,
+				None => {
+					moved.push(index);
+					Some(self.new_group())
+				},
+				group => group,
+			};
+			self.move_cell(index, group);
+		}
+
+		// the cells at either end of every pipe that can no longer be placed
+		let mut ends = vec![];
+		let mut joins = vec![];
+		for &index in &order {
+			for direction in Direction::all() {
+				let Some(neighbour) = field.neighbour(index, direction) else {
+					continue;
+				};
+				let old_neighbour = before.get(&neighbour).copied().unwrap_or(self.sides[neighbour]);
+				let was = has_side(before[&index], direction) && has_side(old_neighbour, direction.opposite());
+				let is = self.joined(field, index, direction).is_some();
+				match (was, is) {
+					(false, true) => joins.push((index, neighbour)),
+					(true, false) => ends.extend([index, neighbour]),
+					_ => {},
+				}
+			}
+		}
+
+		// the smaller group of the two is moved into the other
+		for (a, b) in joins {
+			let (Some(group_a), Some(group_b)) = (self.groups[a], self.groups[b]) else {
+				continue;
+			};
+			if group_a == group_b {
+				continue;
+			}
+			let (start, from, into) = match self.sizes[&group_a] < self.sizes[&group_b] {
+				true => (a, group_a, group_b),
+				false => (b, group_b, group_a),
+			};
+
+			self.move_cell(start, Some(into));
+			moved.push(start);
+			let mut stack = vec![start];
+			while let Some(index) = stack.pop() {
+				for direction in Direction::all() {
+					match self.linked(field, &before, index, direction) {
+						Some(neighbour) if self.groups[neighbour] == Some(from) => {
+							self.move_cell(neighbour, Some(into));
+							moved.push(neighbour);
+							stack.push(neighbour);
+						},
+						_ => {},
+					}
+				}
+			}
+		}
+		for &index in &order {
+			if self.sides[index] == 0 {
+				self.move_cell(index, None);
+			}
+		}
+
+		// ends found apart from each other; every other end is found together with one of them
+		let mut apart: Vec<usize> = vec![];
+		for end in ends {
+			if self.groups[end].is_none() || apart.contains(&end) {
+				continue;
+			}
+
+			let mut together = false;
+			for &other in &apart {
+				if self.groups[other] != self.groups[end] {
+					continue;
+				}
+				match self.search(field, end, other) {
+					None => {
+						together = true;
+						break;
+					},
+					Some(cells) => {
+						let group = self.new_group();
+						for &cell in &cells {
+							self.move_cell(cell, Some(group));
+						}
+						moved.extend(cells);
+					},
+				}
+			}
+			if !together {
+				apart.push(end);
+			}
+		}
+
+		let emptied = order.into_iter().filter(|index| before[index] != 0 && self.sides[*index] == 0).collect();
+		(emptied, Some(moved))
+	}
+	/// The sides the cell may have pipes towards as `Direction` bits, and
+	/// whether it must hold a pipe.
+	fn look<T: Tile>(&self, field: &Field<T>, index: usize) -> (u8, bool) {
+		let states = field.domains[index].states();
+		let sides = Direction::all()
+			.into_iter()
+			.filter(|direction| !(states & self.pipes.connecting[*direction as usize]).is_empty())
+			.fold(0, |sides, direction| sides | 1 << direction as u8);
+
+		(sides, self.pipes.must_hold_pipe(field, index))
+	}
+}
+/// Every pipe of the field belongs to one network: all tiles with pipes are
+/// connected to each other, and there is at least one of them.
+///
+/// Two cells that must hold pipes but are in different groups fail the check,
+/// and cells outside the group of the ones that must hold pipes are left
+/// empty.
+pub struct Connected {
+	grouping: Grouping,
+	/// The group of the cells that must hold pipes, as of the last check.
+	network: Option<usize>,
+}
+impl Connected {
+	pub fn new<T: Pipe>(tiles: &[T]) -> Self {
+		Self {
+			grouping: Grouping::new(Pipes::new(tiles)),
+			network: None,
+		}
+	}
+}
+impl<T: Tile> Constraint<T> for Connected {
+	fn reset(&mut self) {
+		self.grouping.reset();
+		self.network = None;
+	}
+	fn check(&mut self, field: &Field<T>, changed: &[usize]) -> Result<Vec<(usize, StateSet)>, Violation> {
+		let (emptied, moved) = self.grouping.update(field, changed);
+		let grouping = &self.grouping;
+		if grouping.sizes.is_empty() {
+			return Err(Violation {
+				index: emptied.first().or(changed.first()).copied().unwrap_or_default(),
+				reason: "no cell can hold a pipe any more".to_string(),
+			});
+		}
+
+		let last_network = self.network;
+		self.network = match grouping.requiring.len() {
+			0 => None,
+			1 => grouping.requiring.keys().next().copied(),
+			_ => {
+				let mut network = None;
+				let mut first = 0;
+				for index in (0..field.domains.len()).filter(|index| grouping.required[*index]) {
+					match network {
+						None => (network, first) = (grouping.groups[index], index),
+						Some(_) if network != grouping.groups[index] => {
+							let (a, b) = (field.position(first), field.position(index));
+							return Err(Violation {
+								index,
+								reason: format!("the pipes at {:?} and {:?} can no longer be joined", a, b),
+							});
+						},
+						Some(_) => {},
+					}
+				}
+				network
+			},
+		};
+		let Some(network) = self.network else {
+			return Ok(vec![]);
+		};
+		if grouping.sizes.len() == 1 {
+			return Ok(vec![]);
+		}
+
+		// with the same network as before, only cells that moved can be outside it
+		let mut outside: Vec<usize> = match moved {
+			Some(moved) if last_network == Some(network) => moved,
+			_ => (0..field.domains.len()).collect(),
+		};
+		outside.retain(|index| grouping.groups[*index].is_some_and(|group| group != network));
+		outside.sort_unstable();
+		outside.dedup();
+
+		Ok(outside.into_iter().map(|index| (index, grouping.pipes.empty)).collect())
+	}
+}
+
+/// Whether the `Direction` bits hold `direction`.
+fn has_side(sides: u8, direction: Direction) -> bool {
+	sides & 1 << direction as u8 != 0
+}
+/// Takes one from the count of `group`, forgetting it at zero.
+fn uncount(counts: &mut HashMap<usize, usize>, group: usize) {
+	if let Some(count) = counts.get_mut(&group) {
+		*count -= 1;
+		if *count == 0 {
+			counts.remove(&group);
+		}
+	}
+}
+
+/// A pipe runs from the cell at `from` to the one at `to`, both given as
+/// (x, y, z).
+///
+/// Both cells are made to hold pipes, and the check fails once no pipe can
+/// reach `to` from `from` any more, which is once they are no longer in the
+/// same group.
+pub struct Path {
+	grouping: Grouping,
+	from: (usize, usize, usize),
+	to: (usize, usize, usize),
+}
+impl Path {
+	pub fn new<T: Pipe>(tiles: &[T], from: (usize, usize, usize), to: (usize, usize, usize)) -> Self {
+		Self {
+			grouping: Grouping::new(Pipes::new(tiles)),
+			from,
+			to,
+		}
+	}
+}
+impl<T: Tile> Constraint<T> for Path {
+	fn reset(&mut self) {
+		self.grouping.reset();
+	}
+	fn check(&mut self, field: &Field<T>, changed: &[usize]) -> Result<Vec<(usize, StateSet)>, Violation> {
+		let (Ok(from), Ok(to)) = (field.index(self.from.0, self.from.1, self.from.2), field.index(self.to.0, self.to.1, self.to.2)) else {
+			return Err(Violation {
+				index: 0,
+				reason: format!("the path from {:?} to {:?} leaves the field", self.from, self.to),
+			});
+		};
+
+		self.grouping.update(field, changed);
+		let groups = &self.grouping.groups;
+		if from != to && (groups[from].is_none() || groups[from] != groups[to]) {
+			return Err(Violation {
+				index: to,
+				reason: format!("no pipe can run from {:?} to {:?} any more", self.from, self.to),
+			});
+		}
+
+		let pipes = StateSet::full(field.tiles.len()) - self.grouping.pipes.empty;
+		Ok(vec![(from, pipes), (to, pipes)])
+	}
+}
+
+/// The tile with the given index fills at least `min` and at most `max`
+/// cells.
+///
+/// Once `max` cells hold the tile it is removed from all others, and once
+/// only `min` cells could still hold it they all get it. The cells holding
+/// the tile and the ones that still may are counted on the first check and
+/// kept up to date from the cells that changed after that.
+pub struct Count {
+	tile: usize,
+	min: usize,
+	max: usize,
+	/// Whether every cell holds the tile, as of the last check.
+	cells: Vec<Holds>,
+	holding: usize,
+	open: usize,
+}
+/// Whether a cell holds the tile of a `Count`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Holds {
+	Yes,
+	/// Not yet, but it still may.
+	Maybe,
+	No,
+}
+impl Count {
+	pub fn new(tile: usize, min: usize, max: usize) -> Self {
+		Self {
+			tile,
+			min,
+			max,
+			cells: vec![],
+			holding: 0,
+			open: 0,
+		}
+	}
+	fn holds(&self, domain: &Domain) -> Holds {
+		match domain {
+			Domain::Collapsed(tile) if *tile == self.tile => Holds::Yes,
+			Domain::Superposition(candidates) if candidates.states.contains(self.tile) => Holds::Maybe,
+			_ => Holds::No,
+		}
+	}
+	/// Moves the cell from what it held to `holds`, keeping the counts.
+	fn set(&mut self, index: usize, holds: Holds) {
+		use Holds::*;
+
+		match std::mem::replace(&mut self.cells[index], holds) {
+			Yes => self.holding -= 1,
+			Maybe => self.open -= 1,
+			No => {},
+		}
+		match holds {
+			Yes => self.holding += 1,
+			Maybe => self.open += 1,
+			No => {},
+		}
+	}
+	/// The latest of the `changed` cells that holds as given, or else the
+	/// first cell that does.
+	fn blame(&self, changed: &[usize], holds: Holds) -> usize {
+		changed
+			.iter()
+			.rev()
+			.copied()
+			.find(|index| self.cells[*index] == holds)
+			.or_else(|| self.cells.iter().position(|cell| *cell == holds))
+			.unwrap_or_default()
+	}
+}
+impl<T: Tile> Constraint<T> for Count {
+	fn reset(&mut self) {
+		self.cells.clear();
+	}
+	/// Failing checks blame the latest cell to change that took the count
+	/// out of bounds.
+	fn check(&mut self, field: &Field<T>, changed: &[usize]) -> Result<Vec<(usize, StateSet)>, Violation> {
+		if self.cells.len() != field.domains.len() {
+			self.cells = vec![Holds::No; field.domains.len()];
+			(self.holding, self.open) = (0, 0);
+			for index in 0..field.domains.len() {
+				self.set(index, self.holds(&field.domains[index]));
+			}
+		} else {
+			for &index in changed {
+				self.set(index, self.holds(&field.domains[index]));
+			}
+		}
+
+		let name = field.tiles[self.tile].name();
+		if self.holding > self.max {
+			return Err(Violation {
+				index: self.blame(changed, Holds::Yes),
+				reason: format!("{} fills {} cells, at most {} are allowed", name, self.holding, self.max),
+			});
+		}
+		if self.holding + self.open < self.min {
+			return Err(Violation {
+				index: self.blame(changed, Holds::No),
+				reason: format!("{} needs at least {} cells, at most {} are possible", name, self.min, self.holding + self.open),
+			});
+		}
+
+		let allowed = match self.holding {
+			_ if self.open == 0 => return Ok(vec![]),
+			count if count == self.max => StateSet::full(field.tiles.len()) - StateSet::single(self.tile),
+			count if count + self.open == self.min => StateSet::single(self.tile),
+			_ => return Ok(vec![]),
+		};
+		Ok((0..self.cells.len()).filter(|index| self.cells[*index] == Holds::Maybe).map(|index| (index, allowed)).collect())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{Boundary, State};
+
+	/// Tiles that fit next to anything.
+	#[derive(Clone, Debug)]
+	struct Any(usize);
+	impl Tile for Any {
+		fn name(&self) -> String {
+			self.0.to_string()
+		}
+		fn weight(&self) -> usize {
+			1
+		}
+		fn fits(&self, _: &Self, _: Direction) -> bool {
+			true
+		}
+	}
+
+	/// A row of `width` cells with the given cells pinned to tiles.
+	fn field(width: usize, pins: &[(usize, &str)]) -> Field<Any> {
+		let mut field = Field::new(vec![Any(0), Any(1)], width, 1, 1, Boundary::Open).unwrap();
+		for (x, tile) in pins {
+			field.pin(*x, 0, 0, tile).unwrap();
+		}
+
+		field
+	}
+
+	#[test]
+	fn count_removes_tile_at_max() {
+		let field = field(3, &[(0, "0")]);
+		assert_eq!(Count::new(0, 0, 1).check(&field, &[]).unwrap(), [(1, StateSet::single(1)), (2, StateSet::single(1))]);
+		assert_eq!(Count::new(0, 0, 2).check(&field, &[]).unwrap(), []);
+	}
+
+	#[test]
+	fn count_fills_tile_at_min() {
+		let field = field(3, &[(0, "1")]);
+		assert_eq!(Count::new(0, 2, 3).check(&field, &[]).unwrap(), [(1, StateSet::single(0)), (2, StateSet::single(0))]);
+		assert_eq!(Count::new(0, 1, 3).check(&field, &[]).unwrap(), []);
+	}
+
+	#[test]
+	fn count_fails_out_of_bounds() {
+		let field = field(4, &[(1, "0"), (3, "0"), (2, "1")]);
+
+		let violation = Count::new(0, 0, 1).check(&field, &[1, 3, 2]).unwrap_err();
+		assert_eq!(violation.index, 3);
+		assert_eq!(violation.reason, "0 fills 2 cells, at most 1 are allowed");
+
+		let violation = Count::new(0, 4, 4).check(&field, &[1, 2, 3]).unwrap_err();
+		assert_eq!(violation.index, 2);
+		assert_eq!(violation.reason, "0 needs at least 4 cells, at most 3 are possible");
+	}
+
+	#[test]
+	fn count_keeps_counting_changed_cells() {
+		let mut field = field(3, &[]);
+		let mut count = Count::new(0, 0, 1);
+		assert_eq!(count.check(&field, &[0, 1, 2]).unwrap(), []);
+
+		let mark = field.trail.len();
+		field.pin(2, 0, 0, "0").unwrap();
+		assert_eq!(count.check(&field, &[2]).unwrap(), [(0, StateSet::single(1)), (1, StateSet::single(1))]);
+		assert_eq!((count.holding, count.open), (1, 2));
+
+		field.undo(mark);
+		assert_eq!(count.check(&field, &[2]).unwrap(), []);
+		assert_eq!((count.holding, count.open), (0, 3));
+	}
+
+	/// A row of `width` cells of pipes, with the given cells pinned.
+	fn pipes(width: usize, pins: &[(usize, &str)]) -> Field<State> {
+		let mut field = Field::new(State::all().to_vec(), width, 1, 1, Boundary::Open).unwrap();
+		for (x, tile) in pins {
+			field.pin(*x, 0, 0, tile).unwrap();
+		}
+
+		field
+	}
+	/// The groups of `connected` numbered in the order cells first show up in
+	/// them, so groupings can be compared whatever the groups are called.
+	fn grouping(connected: &Connected) -> Vec<Option<usize>> {
+		let mut names: Vec<usize> = vec![];
+		connected
+			.grouping
+			.groups
+			.iter()
+			.map(|group| {
+				let group = (*group)?;
+				if !names.contains(&group) {
+					names.push(group);
+				}
+				names.iter().position(|name| *name == group)
+			})
+			.collect()
+	}
+	fn check(connected: &mut Connected, field: &Field<State>, changed: &[usize]) -> Vec<(usize, StateSet)> {
+		Constraint::<State>::check(connected, field, changed).unwrap()
+	}
+
+	#[test]
+	fn connected_splits_and_joins_groups() {
+		let mut field = pipes(5, &[]);
+		let mut connected = Connected::new(&State::all());
+		assert_eq!(check(&mut connected, &field, &[0, 1, 2, 3, 4]), []);
+		assert_eq!(grouping(&connected), [Some(0); 5]);
+
+		// a vertical pipe in the middle cuts the row in three
+		let mark = field.trail.len();
+		field.pin(2, 0, 0, "BT").unwrap();
+		let changed: Vec<usize> = field.trail[mark..].iter().map(|(index, _)| *index).collect();
+		let empty = StateSet::single(State::Empty as usize);
+		assert_eq!(check(&mut connected, &field, &changed), [(0, empty), (1, empty), (3, empty), (4, empty)]);
+		assert_eq!(grouping(&connected), [Some(0), Some(0), Some(1), Some(2), Some(2)]);
+
+		// and taking it out joins them again
+		field.undo(mark);
+		assert_eq!(check(&mut connected, &field, &changed), []);
+		assert_eq!(grouping(&connected), [Some(0); 5]);
+		assert_eq!(connected.network, None);
+	}
+
+	#[test]
+	fn connected_starts_over_on_another_field() {
+		let mut connected = Connected::new(&State::all());
+		let first = pipes(5, &[(2, "BT")]);
+		check(&mut connected, &first, &[0, 1, 2, 3, 4]);
+
+		// the same field again, before anything was pruned from it
+		let second = pipes(5, &[(2, "BT")]);
+		let mut fresh = Connected::new(&State::all());
+		Constraint::<State>::reset(&mut connected);
+		assert_eq!(check(&mut connected, &second, &[0, 1, 2, 3, 4]), check(&mut fresh, &second, &[0, 1, 2, 3, 4]));
+		assert_eq!(grouping(&connected), grouping(&fresh));
+		assert_eq!(connected.network.is_some(), fresh.network.is_some());
+	}
+
+	#[test]
+	fn path_fails_once_cut() {
+		let mut field = pipes(5, &[]);
+		let mut path = Path::new(&State::all(), (0, 0, 0), (4, 0, 0));
+		assert!(Constraint::<State>::check(&mut path, &field, &[0, 1, 2, 3, 4]).is_ok());
+
+		let mark = field.trail.len();
+		field.pin(2, 0, 0, "BT").unwrap();
+		let changed: Vec<usize> = field.trail[mark..].iter().map(|(index, _)| *index).collect();
+		assert_eq!(Constraint::<State>::check(&mut path, &field, &changed).unwrap_err().index, 4);
+
+		field.undo(mark);
+		assert!(Constraint::<State>::check(&mut path, &field, &changed).is_ok());
+	}
+}
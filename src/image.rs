@@ -0,0 +1,84 @@
+use std::{fmt::Display, fs::File, io::{BufReader, Write}, path::Path};
+
+/// An RGBA image, one color per pixel, row by row.
+#[derive(Clone, Debug)]
+pub struct Image {
+	pub width: usize,
+	pub height: usize,
+	pub pixels: Vec<[u8; 4]>,
+}
+
+#[derive(Debug)]
+pub enum ImageError {
+	Io(std::io::Error),
+	Decode(png::DecodingError),
+}
+impl Display for ImageError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		use ImageError::*;
+
+		match self {
+			Io(e) => write!(f, "{}", e),
+			Decode(e) => write!(f, "{}", e),
+		}
+	}
+}
+
+impl Image {
+	pub fn new(width: usize, height: usize, color: [u8; 4]) -> Self {
+		Self {
+			width,
+			height,
+			pixels: vec![color; width * height],
+		}
+	}
+	/// Reads a PNG image of any color type.
+	pub fn load(path: impl AsRef<Path>) -> Result<Self, ImageError> {
+		let file = File::open(path).map_err(ImageError::Io)?;
+		let mut decoder = png::Decoder::new(BufReader::new(file));
+		decoder.set_transformations(png::Transformations::normalize_to_color8());
+
+		let mut reader = decoder.read_info().map_err(ImageError::Decode)?;
+		let mut buffer = vec![0; reader.output_buffer_size().unwrap_or_default()];
+		let info = reader.next_frame(&mut buffer).map_err(ImageError::Decode)?;
+
+		let channels = info.color_type.samples();
+		let pixels = buffer[..info.buffer_size()]
+			.chunks_exact(info.line_size)
+			.flat_map(|line| line[..info.width as usize * channels].chunks_exact(channels))
+			.map(|pixel| match pixel {
+				[l] => [*l, *l, *l, 255],
+				[l, a] => [*l, *l, *l, *a],
+				[r, g, b] => [*r, *g, *b, 255],
+				[r, g, b, a] => [*r, *g, *b, *a],
+				_ => unreachable!("8 bit colors have 1 to 4 channels"),
+			})
+			.collect();
+
+		Ok(Self {
+			width: info.width as usize,
+			height: info.height as usize,
+			pixels,
+		})
+	}
+	pub fn write_png(&self, out: impl Write) -> std::io::Result<()> {
+		let mut encoder = png::Encoder::new(out, self.width as u32, self.height as u32);
+		encoder.set_color(png::ColorType::Rgba);
+		encoder.set_depth(png::BitDepth::Eight);
+
+		let mut writer = encoder.write_header().map_err(std::io::Error::other)?;
+		writer.write_image_data(self.pixels.as_flattened()).map_err(std::io::Error::other)?;
+
+		writer.finish().map_err(std::io::Error::other)
+	}
+	pub fn get(&self, x: usize, y: usize) -> [u8; 4] {
+		self.pixels[y * self.width + x]
+	}
+	pub fn fill(&mut self, x: usize, y: usize, width: usize, height: usize, color: [u8; 4]) {
+		for y in y..(y + height).min(self.height) {
+			for x in x..(x + width).min(self.width) {
+				self.pixels[y * self.width + x] = color;
+			}
+		}
+	}
+}
@@ -0,0 +1,262 @@
+use std::{fmt::Display, path::Path};
+
+use crate::{state_set::StateSet, Direction, Pipe, Tile};
+
+/// A tile whose neighbours and weight were learned from an example map
+/// instead of taken from the tile itself.
+#[derive(Clone, Debug)]
+pub struct Learned<T: Tile> {
+	tile: T,
+	index: usize,
+	count: usize,
+	/// `neighbours[direction]` holds the indices of every learned tile seen
+	/// next to this one in `direction`.
+	neighbours: [StateSet; 6],
+}
+impl<T: Tile> Tile for Learned<T> {
+	fn name(&self) -> String {
+		self.tile.name()
+	}
+	fn weight(&self) -> usize {
+		self.count
+	}
+	fn fits(&self, other: &Self, direction: Direction) -> bool {
+		self.neighbours[direction as usize].contains(other.index)
+	}
+}
+impl<T: Pipe> Pipe for Learned<T> {
+	fn connects(&self, direction: Direction) -> bool {
+		self.tile.connects(direction)
+	}
+}
+impl<T: Tile + Display> Display for Learned<T> {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(f, "{}", self.tile)
+	}
+}
+
+#[derive(Debug)]
+pub enum LearnError {
+	Io(std::io::Error),
+	Empty,
+	UnknownGlyph { line: usize, column: usize },
+	RaggedRows(usize),
+	/// The level starting at the line has a different number of rows than the
+	/// first.
+	LevelSize(usize),
+	/// The named tiles have the same glyph, so examples can't tell them apart.
+	DuplicateGlyph(String, String),
+}
+impl Display for LearnError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		use LearnError::*;
+
+		match self {
+			Io(e) => write!(f, "Could not read example: {}", e),
+			Empty => write!(f, "Example has no tiles"),
+			UnknownGlyph { line, column } => write!(f, "Example has an unknown glyph at line {}, column {}", line, column),
+			RaggedRows(line) => write!(f, "Line {} of the example has a different number of tiles than the first", line),
+			LevelSize(line) => write!(f, "Level at line {} of the example has a different number of rows than the first", line),
+			DuplicateGlyph(a, b) => write!(f, "Tiles {} and {} have the same glyph, so examples can't tell them apart", a, b),
+		}
+	}
+}
+
+/// Line between two levels of an example.
+pub const LEVEL_SEPARATOR: &str = "---";
+
+/// Splits every line of `example` into the glyphs of `tiles`, which have to
+/// differ from each other, returning the grid of tile indices of every level.
+/// Levels go from the bottom up and are separated by `LEVEL_SEPARATOR` lines. Lines are padded with spaces to the
+/// same length first, since editors like to strip the trailing spaces of
+/// empty tiles. That leaves a row of nothing but empty tiles as an empty line,
+/// so those are rows too.
+fn parse<T: Tile + Display>(example: &str, tiles: &[T]) -> Result<Vec<Vec<Vec<usize>>>, LearnError> {
+	let glyphs: Vec<Vec<char>> = tiles.iter().map(|tile| tile.to_string().chars().collect()).collect();
+	for (i, glyph) in glyphs.iter().enumerate().filter(|(_, glyph)| !glyph.is_empty()) {
+		if let Some(j) = glyphs[..i].iter().position(|other| other == glyph) {
+			return Err(LearnError::DuplicateGlyph(tiles[j].name(), tiles[i].name()));
+		}
+	}
+	let is_separator = |line: &str| line.trim_end() == LEVEL_SEPARATOR;
+	let length = example.lines().filter(|line| !is_separator(line)).map(|line| line.chars().count()).max().unwrap_or(0);
+
+	let mut levels: Vec<Vec<Vec<usize>>> = vec![vec![]];
+	// the line every level starts at
+	let mut starts = vec![0];
+	for (number, line) in example.lines().enumerate() {
+		if is_separator(line) {
+			levels.push(vec![]);
+			starts.push(number + 1);
+			continue;
+		}
+		let mut line: Vec<char> = line.chars().collect();
+		line.resize(length, ' ');
+
+		let mut row = vec![];
+		let mut column = 0;
+		while column < line.len() {
+			// longest glyph first, so a glyph never gets cut short by one it starts with
+			let (index, glyph) = glyphs
+				.iter()
+				.enumerate()
+				.filter(|(_, glyph)| !glyph.is_empty() && line[column..].starts_with(glyph))
+				.max_by_key(|(_, glyph)| glyph.len())
+				.ok_or(LearnError::UnknownGlyph { line: number + 1, column: column + 1 })?;
+			row.push(index);
+			column += glyph.len();
+		}
+
+		if levels[0].first().is_some_and(|first| first.len() != row.len()) {
+			return Err(LearnError::RaggedRows(number + 1));
+		}
+		let level = levels.len() - 1;
+		levels[level].push(row);
+	}
+	if let Some(z) = levels.iter().position(|level| level.len() != levels[0].len()) {
+		return Err(LearnError::LevelSize(starts[z] + 1));
+	}
+
+	if levels[0].is_empty() || levels[0][0].is_empty() {
+		return Err(LearnError::Empty);
+	}
+
+	Ok(levels)
+}
+
+/// Learns from an example map drawn with the glyphs of `tiles` which tiles
+/// may be placed next to each other and how often each one is used.
+///
+/// Only tiles occurring in the example are returned, each allowed next to
+/// exactly the tiles it was seen next to. An example of a single level says
+/// nothing about the levels above and below, so there the tiles keep their
+/// own rules.
+pub fn learn<T: Tile + Display>(example: &str, tiles: &[T]) -> Result<Vec<Learned<T>>, LearnError> {
+	use Direction::*;
+
+	let levels = parse(example, tiles)?;
+
+	// learned tiles are numbered in the order of `tiles`
+	let mut learned_index = vec![None; tiles.len()];
+	let mut learned: Vec<Learned<T>> = vec![];
+	for (i, tile) in tiles.iter().enumerate() {
+		if levels.iter().flatten().flatten().any(|t| *t == i) {
+			learned_index[i] = Some(learned.len());
+			learned.push(Learned {
+				tile: tile.clone(),
+				index: learned.len(),
+				count: 0,
+				neighbours: [StateSet::empty(); 6],
+			});
+		}
+	}
+
+	let learned_at = |x: usize, y: usize, z: usize| learned_index[levels[z][y][x]].unwrap();
+	for z in 0..levels.len() {
+		let rows = &levels[z];
+		for y in 0..rows.len() {
+			for x in 0..rows[y].len() {
+				let current = learned_at(x, y, z);
+				learned[current].count += 1;
+
+				if x + 1 < rows[y].len() {
+					let right = learned_at(x + 1, y, z);
+					learned[current].neighbours[Right as usize].insert(right);
+					learned[right].neighbours[Left as usize].insert(current);
+				}
+				if y + 1 < rows.len() {
+					let bottom = learned_at(x, y + 1, z);
+					learned[current].neighbours[Bottom as usize].insert(bottom);
+					learned[bottom].neighbours[Top as usize].insert(current);
+				}
+				if z + 1 < levels.len() {
+					let up = learned_at(x, y, z + 1);
+					learned[current].neighbours[Up as usize].insert(up);
+					learned[up].neighbours[Down as usize].insert(current);
+				}
+			}
+		}
+	}
+
+	if levels.len() == 1 {
+		for i in 0..learned.len() {
+			for j in 0..learned.len() {
+				for direction in [Up, Down] {
+					if learned[i].tile.fits(&learned[j].tile, direction) {
+						learned[i].neighbours[direction as usize].insert(j);
+					}
+				}
+			}
+		}
+	}
+
+	Ok(learned)
+}
+
+/// Like `learn`, reading the example from a text file.
+pub fn load<T: Tile + Display>(path: impl AsRef<Path>, tiles: &[T]) -> Result<Vec<Learned<T>>, LearnError> {
+	let example = std::fs::read_to_string(path).map_err(LearnError::Io)?;
+
+	learn(&example, tiles)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{tileset::TileDef, State};
+
+	const EMPTY: usize = 0;
+	const BL: usize = 1;
+	const BT: usize = 4;
+	const LR: usize = 9;
+
+	#[test]
+	fn parses_rows() {
+		let levels = parse("━━━━┓ \n    ┃ \n", &State::all()).unwrap();
+		assert_eq!(levels, [[[LR, BL], [EMPTY, BT]]]);
+	}
+
+	#[test]
+	fn pads_short_rows() {
+		let levels = parse("━━━━┓ \n━━━\n", &State::all()).unwrap();
+		assert_eq!(levels, [[[LR, BL], [LR, EMPTY]]]);
+	}
+
+	#[test]
+	fn keeps_blank_rows() {
+		let levels = parse("━━━━━━\n\n━━━━━━\n", &State::all()).unwrap();
+		assert_eq!(levels, [[[LR, LR], [EMPTY, EMPTY], [LR, LR]]]);
+	}
+
+	#[test]
+	fn separates_levels() {
+		let levels = parse("━━━\n---\n ┃ \n", &State::all()).unwrap();
+		assert_eq!(levels, [[[LR]], [[BT]]]);
+
+		assert!(matches!(parse("━━━\n━━━\n---\n ┃ \n", &State::all()), Err(LearnError::LevelSize(4))));
+	}
+
+	#[test]
+	fn counts_lines_with_blank_ones() {
+		assert!(matches!(parse("━━━\n\n━x━\n", &State::all()), Err(LearnError::UnknownGlyph { line: 3, column: 1 })));
+		assert!(matches!(parse("", &State::all()), Err(LearnError::Empty)));
+	}
+
+	#[test]
+	fn rejects_duplicate_glyphs() {
+		let tile = |name: &str, glyph: &str| toml::from_str::<TileDef>(&format!("name = '{}'\nglyph = '{}'", name, glyph)).unwrap();
+		let tiles = [tile("a", "a"), tile("up", "^"), tile("b", "b"), tile("also_up", "^")];
+
+		assert!(matches!(parse("ab\n", &tiles), Err(LearnError::DuplicateGlyph(a, b)) if a == "up" && b == "also_up"));
+	}
+
+	#[test]
+	fn rejects_ragged_rows() {
+		let tile = |name: &str, glyph: &str| toml::from_str::<TileDef>(&format!("name = '{}'\nglyph = '{}'", name, glyph)).unwrap();
+		let tiles = [tile("a", "a"), tile("wide", "bb"), tile("blank", " ")];
+
+		assert!(matches!(parse("aa\nbb\n", &tiles), Err(LearnError::RaggedRows(2))));
+		assert!(matches!(parse("bb\na\n", &tiles), Err(LearnError::RaggedRows(2))));
+		assert_eq!(parse("abb\nbba\n", &tiles).unwrap(), [[[0, 1], [1, 0]]]);
+	}
+}
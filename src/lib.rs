@@ -0,0 +1,1000 @@
+//! Wave function collapse over fields of tiles: the `Tile` trait to fill
+//! them with, the `Solver` that fills them a step at a time and the
+//! constraints, loaders and renderers around it.
+
+use std::{cmp::Ordering, collections::BinaryHeap, fmt::Display, str::FromStr};
+
+use rand::RngCore;
+
+pub mod animation;
+pub mod constraint;
+pub mod image;
+pub mod learn;
+pub mod overlapping;
+pub mod render;
+pub mod solver;
+pub mod state_set;
+pub mod svg;
+pub mod terminal;
+pub mod tileset;
+use solver::{Event, Solver};
+use state_set::{StateSet, MAX_STATES};
+use constraint::{Constraint, Violation};
+
+
+
+/// Natural logarithm built only from basic floating point operations.
+///
+/// `f64::ln` may give slightly different results on different platforms, which
+/// is enough to change which cell gets collapsed, so enthropies use this instead.
+fn portable_ln(x: f64) -> f64 {
+	if x <= 0.0 {
+		return f64::NEG_INFINITY;
+	}
+
+	let bits = x.to_bits();
+	let (mantissa, exponent) = if bits >> 52 == 0 {
+		// subnormal, scale it into the normal range first
+		let scaled = (x * (1u64 << 54) as f64).to_bits();
+		(f64::from_bits((scaled & ((1u64 << 52) - 1)) | (1023u64 << 52)), ((scaled >> 52) as i64) - 1023 - 54)
+	} else {
+		(f64::from_bits((bits & ((1u64 << 52) - 1)) | (1023u64 << 52)), ((bits >> 52) as i64) - 1023)
+	};
+
+	// ln(m) = 2 * atanh((m - 1) / (m + 1)), with |z| <= 1/3 for m in [1, 2)
+	let z = (mantissa - 1.0) / (mantissa + 1.0);
+	let z2 = z * z;
+	let mut term = z;
+	let mut sum = 0.0;
+	for i in 0..24 {
+		sum += term / (2 * i + 1) as f64;
+		term *= z2;
+	}
+
+	2.0 * sum + exponent as f64 * std::f64::consts::LN_2
+}
+
+/// Uniform integer in `0..bound`, drawn straight from the generator's output
+/// so the result does not depend on the sampling code of any `rand` version.
+fn random_below(rng: &mut impl RngCore, bound: u64) -> u64 {
+	let zone = u64::MAX - u64::MAX % bound;
+	loop {
+		let value = rng.next_u64();
+		if value < zone {
+			return value % bound;
+		}
+	}
+}
+
+/// Uniform float in `0.0..1.0`.
+fn random_unit(rng: &mut impl RngCore) -> f64 {
+	(rng.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+}
+
+
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Direction {
+	Left,
+	Right,
+	Top,
+	Bottom,
+	/// To the level above, in 3D fields.
+	Up,
+	/// To the level below, in 3D fields.
+	Down,
+}
+impl Direction {
+	pub fn all() -> [Self; 6] {
+		use Direction::*;
+
+		[Left, Right, Top, Bottom, Up, Down]
+	}
+	/// The directions within a level.
+	pub fn planar() -> [Self; 4] {
+		use Direction::*;
+
+		[Left, Right, Top, Bottom]
+	}
+	pub fn opposite(&self) -> Self {
+		use Direction::*;
+
+		match self {
+			Left => Right,
+			Right => Left,
+			Top => Bottom,
+			Bottom => Top,
+			Up => Down,
+			Down => Up,
+		}
+	}
+}
+
+
+
+/// Anything a `Field` can be filled with.
+pub trait Tile: Clone {
+	/// Unique name of the tile, used to refer to it from e.g. a `Boundary`.
+	fn name(&self) -> String;
+	/// How likely the tile is to be picked relative to the others, at least 1.
+	fn weight(&self) -> usize;
+	/// Whether `other` may be placed next to this tile in `direction`.
+	fn fits(&self, other: &Self, direction: Direction) -> bool;
+}
+
+/// A tile made of pipes running from its center to some of its edges.
+pub trait Pipe: Tile {
+	fn connects(&self, direction: Direction) -> bool;
+}
+
+
+
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum State {
+	Empty,
+	BL,
+	BLT,
+	BLR,
+	BT,
+	BTR,
+	BR,
+	LT,
+	LTR,
+	LR,
+	TR,
+	BLTR,
+}
+impl State {
+	pub fn all() -> [Self; 12] {
+		use State::*;
+
+		[Empty, BL, BLT, BLR, BT, BTR, BR, LT, LTR, LR, TR, BLTR]
+	}
+
+	fn connects_left(&self) -> bool {
+		use State::*;
+
+		matches!(self, BL | BLT | BLR | LT | LTR | LR | BLTR)
+	}
+	fn connects_right(&self) -> bool {
+		use State::*;
+
+		matches!(self, BLR | BTR | BR | LTR | LR | TR | BLTR)
+	}
+	fn connects_top(&self) -> bool {
+		use State::*;
+
+		matches!(self, BLT | BT | BTR | LT | LTR | TR | BLTR)
+	}
+	fn connects_bottom(&self) -> bool {
+		use State::*;
+
+		matches!(self, BL | BLT | BLR | BT | BTR | BR | BLTR)
+	}
+}
+impl Tile for State {
+	fn name(&self) -> String {
+		format!("{:?}", self)
+	}
+	fn weight(&self) -> usize {
+		use State::*;
+
+		match self {
+			Empty => 5,
+			BL => 5,
+			BLT => 5,
+			BLR => 2,
+			BT => 3,
+			BTR => 2,
+			BR => 3,
+			LT => 3,
+			LTR => 2,
+			LR => 3,
+			TR => 3,
+			BLTR => 1,
+		}
+	}
+	fn fits(&self, other: &Self, direction: Direction) -> bool {
+		use Direction::*;
+
+		match direction {
+			Left => self.connects_left() == other.connects_right(),
+			Right => self.connects_right() == other.connects_left(),
+			Top => self.connects_top() == other.connects_bottom(),
+			Bottom => self.connects_bottom() == other.connects_top(),
+			// flat pipes never reach other levels
+			Up | Down => true,
+		}
+	}
+}
+impl Pipe for State {
+	fn connects(&self, direction: Direction) -> bool {
+		use Direction::*;
+
+		match direction {
+			Left => self.connects_left(),
+			Right => self.connects_right(),
+			Top => self.connects_top(),
+			Bottom => self.connects_bottom(),
+			Up | Down => false,
+		}
+	}
+}
+impl Display for State {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		use State::*;
+
+		let glyph = match self {
+			Empty => "   ",
+			BL =>    "━┓ ",
+			BLT =>   "━┫ ",
+			BLR =>   "━┳━",
+			BT =>    " ┃ ",
+			BTR =>   " ┣━",
+			BR =>    " ┏━",
+			LT =>    "━┛ ",
+			LTR =>   "━┻━",
+			LR =>    "━━━",
+			TR =>    " ┗━",
+			BLTR =>  "━╋━",
+		};
+
+		write!(f, "{}", glyph)
+	}
+}
+
+
+
+#[derive(Clone, Debug)]
+pub struct InvalidDomainError;
+impl Display for InvalidDomainError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(f, "Domain has an invalid state")
+	}
+}
+
+/// Per-state data looked up while collapsing and propagating, computed once
+/// from the tiles so the solver never has to go through `Tile` again.
+struct Rules {
+	weights: Vec<usize>,
+	weight_log_weights: Vec<f64>,
+	/// `compatible[direction][state]` holds every state that may be placed next
+	/// to `state` in `direction`.
+	compatible: [Vec<StateSet>; 6],
+}
+impl Rules {
+	/// Takes at most `MAX_STATES` tiles, all with a weight of at least 1.
+	fn new<T: Tile>(tiles: &[T]) -> Self {
+		let weights: Vec<usize> = tiles.iter().map(Tile::weight).collect();
+
+		Self {
+			weight_log_weights: weights.iter().map(|w| *w as f64 * portable_ln(*w as f64)).collect(),
+			weights,
+			compatible: Direction::all().map(|direction| {
+				tiles
+					.iter()
+					.map(|tile| {
+						let mut set = StateSet::empty();
+						for (i, _) in tiles.iter().enumerate().filter(|(_, other)| tile.fits(other, direction)) {
+							set.insert(i);
+						}
+
+						set
+					})
+					.collect()
+			}),
+		}
+	}
+	fn all(&self) -> StateSet {
+		StateSet::full(self.weights.len())
+	}
+	/// States allowed next to any of `states` in `direction`.
+	fn support(&self, states: StateSet, direction: Direction) -> StateSet {
+		let compatible = &self.compatible[direction as usize];
+		let mut support = StateSet::empty();
+		for state in states.iter() {
+			support |= compatible[state];
+		}
+
+		support
+	}
+}
+
+/// Remaining candidate states of an uncollapsed domain.
+///
+/// The weight sums needed for the Shannon enthropy are kept up to date as
+/// states are removed, so the enthropy never has to be recomputed from scratch.
+#[derive(Clone, Copy, Debug)]
+pub struct Candidates {
+	states: StateSet,
+	weight_sum: f64,
+	weight_log_weight_sum: f64,
+}
+impl Candidates {
+	fn new(states: StateSet, rules: &Rules) -> Self {
+		Self {
+			states,
+			weight_sum: states.iter().map(|s| rules.weights[s] as f64).sum(),
+			weight_log_weight_sum: states.iter().map(|s| rules.weight_log_weights[s]).sum(),
+		}
+	}
+	fn len(&self) -> usize {
+		self.states.len()
+	}
+	fn remove(&mut self, removed: StateSet, rules: &Rules) {
+		for state in (removed & self.states).iter() {
+			self.states.remove(state);
+			self.weight_sum -= rules.weights[state] as f64;
+			self.weight_log_weight_sum -= rules.weight_log_weights[state];
+		}
+	}
+	/// Picks one of the candidates with probability proportional to its weight.
+	fn choose_weighted(&self, rng: &mut impl RngCore, rules: &Rules) -> Option<usize> {
+		let total: usize = self.states.iter().map(|s| rules.weights[s]).sum();
+		if total == 0 {
+			return None;
+		}
+
+		let mut target = random_below(rng, total as u64) as usize;
+		for state in self.states.iter() {
+			if target < rules.weights[state] {
+				return Some(state);
+			}
+			target -= rules.weights[state];
+		}
+
+		None
+	}
+	fn enthropy(&self) -> f64 {
+		if self.weight_sum <= 0.0 {
+			return 0.0;
+		}
+
+		portable_ln(self.weight_sum) - self.weight_log_weight_sum / self.weight_sum
+	}
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum Domain {
+	Collapsed(usize),
+	Superposition(Candidates),
+	Invalid,
+}
+impl Domain {
+	fn new(rules: &Rules) -> Self {
+		Domain::Superposition(Candidates::new(rules.all(), rules))
+	}
+	/// Weighted Shannon enthropy of the domain, `0` once it is collapsed.
+	pub fn enthropy(&self) -> Result<f64, InvalidDomainError> {
+		use Domain::*;
+
+		match self {
+			Invalid => Err(InvalidDomainError),
+			Collapsed(_) => Ok(0.0),
+			Superposition(c) => Ok(c.enthropy()),
+		}
+	}
+	/// States the domain can still take.
+	pub fn states(&self) -> StateSet {
+		use Domain::*;
+
+		match self {
+			Invalid => StateSet::empty(),
+			Collapsed(s) => StateSet::single(*s),
+			Superposition(c) => c.states,
+		}
+	}
+	/// Removes every state not in `allowed`, collapsing or invalidating the
+	/// domain if at most one candidate remains. Returns whether anything was
+	/// removed.
+	fn restrict(&mut self, allowed: StateSet, rules: &Rules) -> bool {
+		use Domain::*;
+
+		match self {
+			Collapsed(s) if !allowed.contains(*s) => {
+				*self = Invalid;
+				true
+			},
+			Collapsed(_) => false,
+			Superposition(c) => {
+				let removed = c.states - allowed;
+				if removed.is_empty() {
+					return false;
+				}
+
+				c.remove(removed, rules);
+				match c.states.len() {
+					0 => *self = Invalid,
+					1 => *self = Collapsed(c.states.first().unwrap()),
+					_ => {},
+				}
+				true
+			},
+			Invalid => false,
+		}
+	}
+	/// Removes `state` from the candidates.
+	fn ban(&mut self, state: usize, rules: &Rules) {
+		self.restrict(self.states() - StateSet::single(state), rules);
+	}
+	fn collapse(&mut self, rng: &mut impl RngCore, rules: &Rules) {
+		use Domain::*;
+
+		match self {
+			Collapsed(_) => {},
+			Superposition(c) => {
+				*self = match c.choose_weighted(rng, rules) {
+					Some(s) => Collapsed(s),
+					None => Invalid,
+				};
+			},
+			Invalid => {},
+		}
+
+	}
+}
+#[derive(Debug, Clone)]
+pub struct UnknownTileError(pub String);
+impl Display for UnknownTileError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(f, "Unknown tile {}", self.0)
+	}
+}
+
+/// Why a `Field` could not be made from its tiles.
+#[derive(Debug, Clone)]
+pub enum FieldError {
+	/// The boundary names a tile that is not one of them.
+	UnknownTile(UnknownTileError),
+	TooManyTiles(usize),
+	/// The named tile has a weight of 0.
+	ZeroWeight(String),
+}
+impl Display for FieldError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		use FieldError::*;
+
+		match self {
+			UnknownTile(e) => write!(f, "{}", e),
+			TooManyTiles(count) => write!(f, "Field has {} tiles, at most {} are supported", count, MAX_STATES),
+			ZeroWeight(name) => write!(f, "Tile {} has a weight of 0", name),
+		}
+	}
+}
+impl From<UnknownTileError> for FieldError {
+	fn from(e: UnknownTileError) -> Self {
+		FieldError::UnknownTile(e)
+	}
+}
+
+#[derive(Debug, Clone)]
+pub struct OutOfBoundsError;
+impl Display for OutOfBoundsError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(f, "Index out of bounds")
+	}
+}
+
+#[derive(Debug, Clone)]
+pub enum PinError {
+	OutOfBounds(usize, usize, usize),
+	UnknownTile(UnknownTileError),
+	/// Pinning left the cell at (x, y, z) without tiles.
+	Contradiction(usize, usize, usize),
+}
+impl Display for PinError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		use PinError::*;
+
+		match self {
+			OutOfBounds(x, y, z) => write!(f, "Cell ({}, {}, {}) is outside the field", x, y, z),
+			UnknownTile(e) => write!(f, "{}", e),
+			Contradiction(x, y, z) => write!(f, "Pins do not fit each other or the boundary, cell ({}, {}, {}) has no tiles left", x, y, z),
+		}
+	}
+}
+
+/// Why generation stopped, with the constraint that failed last if it was
+/// one of them rather than a cell running out of tiles.
+#[derive(Debug, Clone)]
+pub enum GenerationError {
+	Contradiction(Option<Violation>),
+	BacktrackLimitReached(usize, Option<Violation>),
+}
+impl Display for GenerationError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		use GenerationError::*;
+
+		match self {
+			Contradiction(None) => write!(f, "Field has no valid solution"),
+			Contradiction(Some(violation)) => write!(f, "Field has no valid solution: {}", violation),
+			BacktrackLimitReached(limit, None) => write!(f, "Gave up after {} backtracks", limit),
+			BacktrackLimitReached(limit, Some(violation)) => write!(f, "Gave up after {} backtracks, the last contradiction: {}", limit, violation),
+		}
+	}
+}
+impl From<InvalidDomainError> for GenerationError {
+	fn from(_: InvalidDomainError) -> Self {
+		GenerationError::Contradiction(None)
+	}
+}
+
+/// What lies beyond the edges of a `Field`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Boundary {
+	/// Nothing, cells on the edge are not constrained from outside.
+	Open,
+	/// The field wraps around, the left column touches the right one and the
+	/// top row touches the bottom one.
+	Periodic,
+	/// Each side is lined with virtual cells of the named tile, which edge
+	/// cells have to fit just like a real neighbour. Above and below the field
+	/// there only are such cells if tiles are named for them.
+	Closed {
+		left: String,
+		right: String,
+		top: String,
+		bottom: String,
+		up: Option<String>,
+		down: Option<String>,
+	},
+}
+impl Boundary {
+	/// Closed boundary with nothing but `Empty` around the field, so no pipe
+	/// can leave it.
+	pub fn closed() -> Self {
+		let empty = format!("{:?}", State::Empty);
+
+		Boundary::Closed {
+			left: empty.clone(),
+			right: empty.clone(),
+			top: empty.clone(),
+			bottom: empty.clone(),
+			up: Some(empty.clone()),
+			down: Some(empty),
+		}
+	}
+	/// Name of the tile beyond the edge in `direction`, if any.
+	fn outside(&self, direction: Direction) -> Option<&str> {
+		use Direction::*;
+
+		match (self, direction) {
+			(Boundary::Closed { left, .. }, Left) => Some(left),
+			(Boundary::Closed { right, .. }, Right) => Some(right),
+			(Boundary::Closed { top, .. }, Top) => Some(top),
+			(Boundary::Closed { bottom, .. }, Bottom) => Some(bottom),
+			(Boundary::Closed { up, .. }, Up) => up.as_deref(),
+			(Boundary::Closed { down, .. }, Down) => down.as_deref(),
+			_ => None,
+		}
+	}
+}
+impl FromStr for Boundary {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s.split_once('=') {
+			None if s == "open" => Ok(Boundary::Open),
+			None if s == "periodic" => Ok(Boundary::Periodic),
+			None if s == "closed" => Ok(Boundary::closed()),
+			// either one tile for all sides or one per side, with or without the
+			// ones above and below
+			Some(("closed", tiles)) => match tiles.split(',').collect::<Vec<&str>>()[..] {
+				[all] => Ok(Boundary::Closed {
+					left: all.to_string(),
+					right: all.to_string(),
+					top: all.to_string(),
+					bottom: all.to_string(),
+					up: Some(all.to_string()),
+					down: Some(all.to_string()),
+				}),
+				[left, right, top, bottom] => Ok(Boundary::Closed {
+					left: left.to_string(),
+					right: right.to_string(),
+					top: top.to_string(),
+					bottom: bottom.to_string(),
+					up: None,
+					down: None,
+				}),
+				[left, right, top, bottom, up, down] => Ok(Boundary::Closed {
+					left: left.to_string(),
+					right: right.to_string(),
+					top: top.to_string(),
+					bottom: bottom.to_string(),
+					up: Some(up.to_string()),
+					down: Some(down.to_string()),
+				}),
+				_ => Err("A closed boundary needs either 1, 4 or 6 tiles".to_string()),
+			},
+			_ => Err(format!("Unknown boundary {}", s)),
+		}
+	}
+}
+
+/// Entry of the queue `Field::collapse_random` picks the next cell from.
+///
+/// Entries are never removed when a domain changes; instead a fresh one is
+/// pushed and the outdated ones are skipped once they reach the top.
+#[derive(PartialEq)]
+struct QueueEntry {
+	priority: f64,
+	enthropy: f64,
+	index: usize,
+}
+impl Eq for QueueEntry {}
+impl Ord for QueueEntry {
+	fn cmp(&self, other: &Self) -> Ordering {
+		// reversed, so the lowest priority ends up on top of the heap
+		other.priority.total_cmp(&self.priority).then(other.index.cmp(&self.index))
+	}
+}
+impl PartialOrd for QueueEntry {
+	fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+		Some(self.cmp(other))
+	}
+}
+
+pub struct Field<T: Tile> {
+	width: usize,
+	height: usize,
+	/// Number of levels, 1 for a flat field.
+	depth: usize,
+	boundary: Boundary,
+	tiles: Vec<T>,
+	rules: Rules,
+	domains: Vec<Domain>,
+	/// Previous values of every overwritten domain, newest last.
+	trail: Vec<(usize, Domain)>,
+	/// Domains that shrank and whose neighbours still have to be checked.
+	pending: Vec<usize>,
+	/// Domains that changed since the queue was last updated.
+	dirty: Vec<usize>,
+	queue: BinaryHeap<QueueEntry>,
+}
+impl<T: Tile> Field<T> {
+	/// A field of `depth` levels of `width`×`height` cells. Cells are stored
+	/// level by level, each one row by row.
+	///
+	/// Fails with more than `MAX_STATES` tiles, a tile with a weight of 0 or a
+	/// boundary naming a tile that isn't one of them.
+	pub fn new(tiles: Vec<T>, width: usize, height: usize, depth: usize, boundary: Boundary) -> Result<Self, FieldError> {
+		if tiles.len() > MAX_STATES {
+			return Err(FieldError::TooManyTiles(tiles.len()));
+		}
+		if let Some(tile) = tiles.iter().find(|tile| tile.weight() == 0) {
+			return Err(FieldError::ZeroWeight(tile.name()));
+		}
+		let rules = Rules::new(&tiles);
+		let cells = width * height * depth;
+
+		let mut field = Self {
+			width,
+			height,
+			depth,
+			boundary,
+			tiles,
+			domains: vec![Domain::new(&rules); cells],
+			rules,
+			trail: vec![],
+			pending: vec![],
+			dirty: (0..cells).collect(),
+			queue: BinaryHeap::new(),
+		};
+
+		for index in 0..cells {
+			for direction in Direction::all() {
+				if field.neighbour(index, direction).is_some() {
+					continue;
+				}
+				let Some(outside) = field.boundary.outside(direction) else {
+					continue;
+				};
+
+				let outside = field.index_of(outside)?;
+				let allowed = field.rules.compatible[direction.opposite() as usize][outside];
+				if field.domains[index].restrict(allowed, &field.rules) {
+					field.pending.push(index);
+				}
+			}
+		}
+
+		Ok(field)
+	}
+	pub fn width(&self) -> usize {
+		self.width
+	}
+	pub fn height(&self) -> usize {
+		self.height
+	}
+	pub fn depth(&self) -> usize {
+		self.depth
+	}
+	pub fn tiles(&self) -> &[T] {
+		&self.tiles
+	}
+	/// Index of the tile with the given name.
+	fn index_of(&self, name: &str) -> Result<usize, UnknownTileError> {
+		self.tiles
+			.iter()
+			.position(|tile| tile.name() == name)
+			.ok_or_else(|| UnknownTileError(name.to_string()))
+	}
+	fn set(&mut self, index: usize, domain: Domain) {
+		let old = std::mem::replace(&mut self.domains[index], domain);
+		self.trail.push((index, old));
+		self.pending.push(index);
+		self.dirty.push(index);
+	}
+	/// Restores every domain changed since the trail had the given length.
+	fn undo(&mut self, trail_length: usize) {
+		while self.trail.len() > trail_length {
+			if let Some((index, domain)) = self.trail.pop() {
+				self.domains[index] = domain;
+				self.dirty.push(index);
+			}
+		}
+		self.pending.clear();
+	}
+	pub fn neighbour(&self, index: usize, direction: Direction) -> Option<usize> {
+		use Direction::*;
+
+		let (x, y, z) = self.position(index);
+		let level = self.width * self.height;
+		let wraps = matches!(self.boundary, Boundary::Periodic);
+		match direction {
+			Left if x > 0 => Some(index - 1),
+			Left if wraps => Some(index + self.width - 1),
+			Right if x + 1 < self.width => Some(index + 1),
+			Right if wraps => Some(index + 1 - self.width),
+			Top if y > 0 => Some(index - self.width),
+			Top if wraps => Some(index + (self.height - 1) * self.width),
+			Bottom if y + 1 < self.height => Some(index + self.width),
+			Bottom if wraps => Some(index - (self.height - 1) * self.width),
+			Up if z + 1 < self.depth => Some(index + level),
+			// a flat field doesn't wrap onto itself
+			Up if wraps && self.depth > 1 => Some(index - z * level),
+			Down if z > 0 => Some(index - level),
+			Down if wraps && self.depth > 1 => Some(index + (self.depth - 1) * level),
+			_ => None,
+		}
+	}
+	/// The (x, y, z) of the cell with the given index.
+	pub fn position(&self, index: usize) -> (usize, usize, usize) {
+		let level = self.width * self.height;
+
+		(index % self.width, index % level / self.width, index / level)
+	}
+	/// Index of the cell at (`x`, `y`, `z`), if it is in the field.
+	pub fn index(&self, x: usize, y: usize, z: usize) -> Result<usize, OutOfBoundsError> {
+		match x < self.width && y < self.height && z < self.depth {
+			true => Ok((z * self.height + y) * self.width + x),
+			false => Err(OutOfBoundsError),
+		}
+	}
+	pub fn get(&self, x: usize, y: usize, z: usize) -> Result<&Domain, OutOfBoundsError> {
+		self.index(x, y, z).map(|index| &self.domains[index])
+	}
+	/// Allows only the named tiles in the cell at (`x`, `y`, `z`) and
+	/// propagates right away. If that leaves any cell without tiles the field
+	/// is left as it was.
+	pub fn restrict(&mut self, x: usize, y: usize, z: usize, names: &[&str]) -> Result<(), PinError> {
+		let index = self.index(x, y, z).map_err(|_| PinError::OutOfBounds(x, y, z))?;
+
+		let mut allowed = StateSet::empty();
+		for name in names {
+			allowed.insert(self.index_of(name).map_err(PinError::UnknownTile)?);
+		}
+
+		let contradiction = |field: &Self| {
+			let (x, y, z) = field.position(field.invalid().unwrap_or(index));
+			PinError::Contradiction(x, y, z)
+		};
+		// whatever is left from setting up the field, so undoing can't lose it
+		self.propagate().map_err(|_| contradiction(self))?;
+
+		let trail_length = self.trail.len();
+		let mut domain = self.domains[index];
+		if domain.restrict(allowed, &self.rules) {
+			self.set(index, domain);
+		}
+		if self.propagate().is_err() {
+			let error = contradiction(self);
+			self.undo(trail_length);
+			return Err(error);
+		}
+
+		Ok(())
+	}
+	/// Allows only the named tile in the cell at (`x`, `y`, `z`), see
+	/// `restrict`.
+	pub fn pin(&mut self, x: usize, y: usize, z: usize, name: &str) -> Result<(), PinError> {
+		self.restrict(x, y, z, &[name])
+	}
+	/// Collapses the domain with the lowest Shannon enthropy and returns its
+	/// index. A little random noise is added to every enthropy so ties are
+	/// broken at random.
+	fn collapse_random(&mut self, rng: &mut impl RngCore) -> Result<Option<usize>, InvalidDomainError> {
+		const NOISE: f64 = 1e-6;
+
+		for index in std::mem::take(&mut self.dirty) {
+			if let Domain::Collapsed(_) = self.domains[index] {
+				continue;
+			}
+
+			let enthropy = self.domains[index].enthropy()?;
+			self.queue.push(QueueEntry {
+				priority: enthropy + random_unit(rng) * NOISE,
+				enthropy,
+				index,
+			});
+		}
+
+		while let Some(entry) = self.queue.pop() {
+			match &self.domains[entry.index] {
+				Domain::Superposition(c) if c.enthropy() == entry.enthropy => {
+					let mut domain = self.domains[entry.index];
+					domain.collapse(rng, &self.rules);
+					self.set(entry.index, domain);
+
+					return Ok(Some(entry.index));
+				},
+				_ => {},
+			}
+		}
+
+		Ok(None)
+
+	}
+	/// Removes candidates that no longer fit their neighbours, starting from
+	/// the domains that changed since the last call and only moving on to the
+	/// neighbours of domains that actually shrank.
+	fn propagate(&mut self) -> Result<(), InvalidDomainError> {
+		use Domain::*;
+
+		while let Some(index) = self.pending.pop() {
+			if let Invalid = self.domains[index] {
+				self.pending.clear();
+				return Err(InvalidDomainError);
+			}
+			let current = self.domains[index].states();
+
+			for direction in Direction::all() {
+				let Some(neighbour) = self.neighbour(index, direction) else {
+					continue;
+				};
+
+				let mut domain = self.domains[neighbour];
+				if domain.restrict(self.rules.support(current, direction), &self.rules) {
+					self.set(neighbour, domain);
+				}
+			}
+		}
+
+		Ok(())
+	}
+	/// Index of a domain without any states left, if there is one.
+	pub fn invalid(&self) -> Option<usize> {
+		self.domains.iter().position(|domain| matches!(domain, Domain::Invalid))
+	}
+	/// Collapses and propagates until every domain is collapsed, with a
+	/// `Solver` keeping to the constraints and allowed `max_backtracks`
+	/// rewinds.
+	///
+	/// `observe` is called with the field and the events of every step,
+	/// including the one that failed if generation gives up.
+	pub fn generate(&mut self, rng: &mut impl RngCore, constraints: &mut [Box<dyn Constraint<T>>], max_backtracks: usize, mut observe: impl FnMut(&Self, &[Event])) -> Result<(), GenerationError> {
+		let mut solver = Solver::new(self, rng, constraints, max_backtracks);
+
+		loop {
+			let result = solver.step();
+			if let Ok(false) = result {
+				return Ok(());
+			}
+			observe(solver.field(), solver.events());
+			result?;
+		}
+	}
+}
+impl<T: Tile + Display> Display for Field<T> {
+	/// Writes the levels from the bottom up, separated by lines the way
+	/// examples separate them.
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		for z in 0..self.depth {
+			if z > 0 {
+				writeln!(f, "{}", learn::LEVEL_SEPARATOR)?;
+			}
+			for y in 0..self.height {
+				for x in 0..self.width {
+					match self.get(x, y, z).unwrap() {
+						Domain::Collapsed(s) => write!(f, "{}", self.tiles[*s])?,
+						Domain::Superposition(c) => write!(f, "{}", c.len())?,
+						Domain::Invalid => write!(f, "!")?,
+					}
+				}
+				writeln!(f)?;
+			}
+		}
+
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn portable_ln_matches_ln() {
+		let values = [1.0, 2.0, 0.5, std::f64::consts::E, 3.0, 7.25, 1e-3, 123456.789, 1e300, f64::MIN_POSITIVE, 5e-324, f64::MAX];
+		for x in values {
+			let (portable, ln) = (portable_ln(x), x.ln());
+			assert!((portable - ln).abs() <= 1e-14 * ln.abs().max(1.0), "ln({}) = {}, not {}", x, ln, portable);
+		}
+		assert_eq!(portable_ln(1.0), 0.0);
+		assert_eq!(portable_ln(0.0), f64::NEG_INFINITY);
+	}
+
+	/// A tile that fits anywhere, with the given weight.
+	#[derive(Clone, Debug)]
+	struct Weighted(usize);
+	impl Tile for Weighted {
+		fn name(&self) -> String {
+			format!("weight {}", self.0)
+		}
+		fn weight(&self) -> usize {
+			self.0
+		}
+		fn fits(&self, _: &Self, _: Direction) -> bool {
+			true
+		}
+	}
+
+	#[test]
+	fn field_rejects_unusable_tiles() {
+		let field = Field::new(vec![Weighted(1), Weighted(0)], 2, 2, 1, Boundary::Open);
+		assert!(matches!(field, Err(FieldError::ZeroWeight(name)) if name == "weight 0"));
+
+		let field = Field::new(vec![Weighted(1); MAX_STATES + 1], 2, 2, 1, Boundary::Open);
+		assert!(matches!(field, Err(FieldError::TooManyTiles(count)) if count == MAX_STATES + 1));
+
+		assert!(Field::new(vec![Weighted(1); MAX_STATES], 2, 2, 1, Boundary::Open).is_ok());
+	}
+
+	/// Tiles that only fit between the ones numbered one higher or lower to
+	/// their left and right.
+	#[derive(Clone, Debug)]
+	struct Step(usize);
+	impl Tile for Step {
+		fn name(&self) -> String {
+			self.0.to_string()
+		}
+		fn weight(&self) -> usize {
+			1
+		}
+		fn fits(&self, other: &Self, direction: Direction) -> bool {
+			!matches!(direction, Direction::Left | Direction::Right) || self.0.abs_diff(other.0) == 1
+		}
+	}
+
+	#[test]
+	fn contradicting_pin_leaves_field_as_it_was() {
+		let steps = || {
+			let boundary = Boundary::from_str("closed=0,0,0,0").unwrap();
+			Field::new(vec![Step(0), Step(1), Step(2)], 7, 1, 1, boundary).unwrap()
+		};
+		let states = |field: &Field<Step>| field.domains.iter().map(|domain| domain.states().iter().collect()).collect::<Vec<Vec<usize>>>();
+
+		// the boundary only restricts the cells at the edges until propagated
+		let mut setup = steps();
+		setup.propagate().unwrap();
+		assert_eq!(states(&setup), [vec![1], vec![0, 2], vec![1], vec![0, 2], vec![1], vec![0, 2], vec![1]]);
+
+		let mut field = steps();
+		assert!(matches!(field.pin(3, 0, 0, "1"), Err(PinError::Contradiction(3, 0, 0))));
+		assert_eq!(states(&field), states(&setup));
+
+		field.pin(3, 0, 0, "2").unwrap();
+		assert_eq!(states(&field), [vec![1], vec![0, 2], vec![1], vec![2], vec![1], vec![0, 2], vec![1]]);
+	}
+}
@@ -1,335 +1,472 @@
-use std::fmt::Display;
-
-use rand::{seq::SliceRandom};
-use enum_derived::Rand;
-
-
-
-#[derive(Clone, Copy, Rand, Debug, PartialEq)]
-enum State {
-	#[weight(5)] Empty,
-	#[weight(5)] BL,
-	#[weight(5)] BLT,
-	#[weight(2)] BLR,
-	#[weight(3)] BT,
-	#[weight(2)] BTR,
-	#[weight(3)] BR,
-	#[weight(3)] LT,
-	#[weight(2)] LTR,
-	#[weight(3)] LR,
-	#[weight(3)] TR,
-	#[weight(1)] BLTR,
+use std::{fmt::Display, io::{IsTerminal, Write}, path::PathBuf, process::ExitCode, str::FromStr, time::Duration};
+
+use clap::{error::ErrorKind, CommandFactory, Parser, ValueEnum};
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+
+use wfc::{learn, overlapping, render, svg, Boundary, Direction, Field, GenerationError, Pipe, State, Tile, UnknownTileError};
+use wfc::animation::Recorder;
+use wfc::constraint::{Connected, Constraint, Count, Path};
+use wfc::render::{Atlas, Draw, Style};
+use wfc::svg::Vector;
+use wfc::terminal::Animation;
+use wfc::tileset::Tileset;
+
+/// Reads a cell written `X,Y`, or `X,Y,Z` in 3D fields.
+fn parse_cell(cell: &str) -> Result<(usize, usize, usize), String> {
+	let coordinate = |coordinate: &str| coordinate.trim().parse().map_err(|_| format!("{} is not a coordinate", coordinate));
+
+	match cell.split(',').collect::<Vec<&str>>()[..] {
+		[x, y] => Ok((coordinate(x)?, coordinate(y)?, 0)),
+		[x, y, z] => Ok((coordinate(x)?, coordinate(y)?, coordinate(z)?)),
+		_ => Err(format!("{} is not a cell like X,Y or X,Y,Z", cell)),
+	}
 }
-impl State {
-	fn all() -> Vec<Self> {
-		use State::*;
 
-		vec![Empty, BL, BLT, BLR, BT, BTR, BR, LT, LTR, LR, TR, BLTR]
-	}
-	fn count() -> usize {
-		Self::all().len()
-	}
+/// Tiles a cell is limited to before generating, written `X,Y=TILE` or
+/// `X,Y=TILE,TILE,...`, with `X,Y,Z` for cells of 3D fields.
+#[derive(Clone, Debug)]
+struct Pin {
+	cell: (usize, usize, usize),
+	tiles: Vec<String>,
+}
+impl FromStr for Pin {
+	type Err = String;
 
-	fn connects_left(&self) -> bool {
-		use State::*;
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let (cell, tiles) = s.split_once('=').ok_or(format!("{} is not a pin like X,Y=TILE", s))?;
 
-		matches!(self, BL | BLT | BLR | LT | LTR | LR | BLTR)
+		Ok(Pin {
+			cell: parse_cell(cell)?,
+			tiles: tiles.split(',').map(|tile| tile.trim().to_string()).collect(),
+		})
 	}
-	fn connects_right(&self) -> bool {
-		use State::*;
+}
 
-		matches!(self, BLR | BTR | BR | LTR | LR | TR | BLTR)
-	}
-	fn connects_top(&self) -> bool {
-		use State::*;
+/// Two cells a pipe has to run between, written `X,Y:X,Y`, with `X,Y,Z` for
+/// cells of 3D fields.
+#[derive(Clone, Debug)]
+struct Route {
+	from: (usize, usize, usize),
+	to: (usize, usize, usize),
+}
+impl FromStr for Route {
+	type Err = String;
 
-		matches!(self, BLT | BT | BTR | LT | LTR | TR | BLTR)
-	}
-	fn connects_bottom(&self) -> bool {
-		use State::*;
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let (from, to) = s.split_once(':').ok_or(format!("{} is not a route like X,Y:X,Y", s))?;
 
-		matches!(self, BL | BLT | BLR | BT | BTR | BR | BLTR)
+		Ok(Route {
+			from: parse_cell(from)?,
+			to: parse_cell(to)?,
+		})
 	}
+}
 
-	fn fits_left(&self, other: &State) -> bool {
-		self.connects_left() == other.connects_right()
-	}
-	fn fits_bottom(&self, other: &State) -> bool {
-		self.connects_bottom() == other.connects_top()
-	}
+/// A number of cells, or a share of all cells in percent.
+#[derive(Clone, Copy, Debug)]
+enum Amount {
+	Cells(usize),
+	Percent(f64),
+}
+impl FromStr for Amount {
+	type Err = String;
 
-	fn fits_right(&self, other: &State) -> bool {
-		other.fits_left(self)
-	}
-	fn fits_top(&self, other: &State) -> bool {
-		other.fits_bottom(self)
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s.trim().strip_suffix('%') {
+			Some(percent) => percent.trim().parse().map(Amount::Percent).map_err(|_| format!("{} is not a percentage", s)),
+			None => s.trim().parse().map(Amount::Cells).map_err(|_| format!("{} is not a number of cells", s)),
+		}
 	}
-	
 }
-impl ToString for State {
-	fn to_string(&self) -> String {
-		use State::*;
 
-		match self {
-			Empty => "   ",
-			BL =>    "━┓ ",
-			BLT =>   "━┫ ",
-			BLR =>   "━┳━",
-			BT =>    " ┃ ",
-			BTR =>   " ┣━",
-			BR =>    " ┏━",
-			LT =>    "━┛ ",
-			LTR =>   "━┻━",
-			LR =>    "━━━",
-			TR =>    " ┗━",
-			BLTR =>  "━╋━",
-		}.to_string()
+/// How often a tile has to appear, written `TILE=N` for exactly `N` cells or
+/// `TILE=MIN..MAX` with either end left out for no limit. Amounts ending in
+/// `%` are shares of the whole field.
+#[derive(Clone, Debug)]
+struct TileCount {
+	tile: String,
+	min: Option<Amount>,
+	max: Option<Amount>,
+}
+impl TileCount {
+	/// The least and most cells the tile may fill in a field of `cells` cells.
+	fn range(&self, cells: usize) -> (usize, usize) {
+		let min = match self.min {
+			None => 0,
+			Some(Amount::Cells(count)) => count,
+			Some(Amount::Percent(percent)) => (percent * cells as f64 / 100.0).ceil() as usize,
+		};
+		let max = match self.max {
+			None => cells,
+			Some(Amount::Cells(count)) => count,
+			Some(Amount::Percent(percent)) => (percent * cells as f64 / 100.0).floor() as usize,
+		};
+
+		(min, max)
+	}
+}
+impl FromStr for TileCount {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let (tile, amount) = s.split_once('=').ok_or(format!("{} is not a count like TILE=MIN..MAX", s))?;
+		let bound = |amount: &str| match amount.trim() {
+			"" => Ok(None),
+			amount => amount.parse().map(Some),
+		};
+		let (min, max) = match amount.split_once("..") {
+			Some((min, max)) => (bound(min)?, bound(max)?),
+			None => (bound(amount)?, bound(amount)?),
+		};
+
+		Ok(TileCount {
+			tile: tile.trim().to_string(),
+			min,
+			max,
+		})
 	}
 }
 
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum Format {
+	/// The tile glyphs as text
+	Text,
+	/// A PNG image with the tiles drawn as pipes or taken from an atlas
+	Png,
+	/// An SVG image with the connections of tiles drawn as strokes
+	Svg,
+	/// An animated GIF of the map being generated, one frame per collapse
+	Gif,
+}
 
+/// Generates maps with wave function collapse.
+#[derive(Parser, Debug)]
+#[command(after_help = "Exit codes: 0 on success, 1 if no attempt produced a map, 2 for invalid arguments, 3 for unreadable or invalid input files or pins and 4 if the output could not be written.")]
+struct Args {
+	/// Width of the map in tiles
+	#[arg(long, default_value_t = 15, value_parser = parse_size)]
+	width: usize,
+	/// Height of the map in tiles
+	#[arg(long, default_value_t = 15, value_parser = parse_size)]
+	height: usize,
+	/// Number of levels of the map, stacked on top of each other and joined by
+	/// the up and down sockets of tiles
+	#[arg(long, default_value_t = 1, value_parser = parse_size)]
+	depth: usize,
+	/// Seed of the first attempt, random if not given
+	#[arg(long)]
+	seed: Option<u64>,
+	/// Attempts to make before giving up, each one with the seed after the last
+	#[arg(long, default_value_t = 1)]
+	attempts: u64,
+	/// Contradictions to backtrack from per attempt, 0 to give up on the first one
+	#[arg(long, default_value_t = 1000)]
+	backtracks: usize,
+	/// What lies beyond the edges: open, periodic, closed or closed=TILE,
+	/// closed=LEFT,RIGHT,TOP,BOTTOM or closed=LEFT,RIGHT,TOP,BOTTOM,UP,DOWN to
+	/// line the sides with the named tiles. Closed boundaries also close the
+	/// levels above and below the map, except for closed=LEFT,RIGHT,TOP,BOTTOM
+	/// which leaves those open
+	#[arg(long, default_value = "open")]
+	boundary: Boundary,
+	/// Pin the cell at X,Y to a tile with X,Y=TILE or to one of several with
+	/// X,Y=TILE,TILE,..., cells of 3D maps are X,Y,Z; may be given more than
+	/// once
+	#[arg(long)]
+	pin: Vec<Pin>,
+	/// Only make maps whose pipes all join up into one network
+	#[arg(long, conflicts_with = "sample")]
+	connected: bool,
+	/// Only make maps with a pipe running between the cells X,Y and X,Y; may be
+	/// given more than once
+	#[arg(long, conflicts_with = "sample")]
+	path: Vec<Route>,
+	/// Only make maps where a tile fills exactly N cells with TILE=N, or
+	/// between MIN and MAX cells with TILE=MIN..MAX; either end may be left
+	/// out and amounts like 10% are shares of the map. May be given more than
+	/// once
+	#[arg(long)]
+	count: Vec<TileCount>,
+	/// TOML file with the tiles to use instead of the built in pipes
+	#[arg(long)]
+	tileset: Option<PathBuf>,
+	/// Text file with an example map to learn which tiles fit together from;
+	/// maps of several levels have a line of --- between two levels
+	#[arg(long)]
+	example: Option<PathBuf>,
+	/// PNG image to learn overlapping patterns from instead of using tiles
+	#[arg(long, conflicts_with_all = ["tileset", "example"])]
+	sample: Option<PathBuf>,
+	/// Width and height of the patterns learned from the sample
+	#[arg(long, default_value_t = 3, requires = "sample", value_parser = parse_size)]
+	pattern_size: usize,
+	/// Also learn the rotated versions of every pattern
+	#[arg(long, requires = "sample")]
+	rotations: bool,
+	/// Also learn the mirrored versions of every pattern
+	#[arg(long, requires = "sample")]
+	reflections: bool,
+	/// Format of the generated map
+	#[arg(long, value_enum, default_value_t = Format::Text)]
+	format: Format,
+	/// Width and height of a tile in pixels in images
+	#[arg(long, default_value_t = 16, value_parser = parse_size)]
+	cell_size: usize,
+	/// Width of the pipes in pixels in images, a quarter of the cell size by default
+	#[arg(long)]
+	line_width: Option<usize>,
+	/// Background color of images as #rrggbb or #rrggbbaa
+	#[arg(long, value_parser = render::parse_color)]
+	background: Option<[u8; 4]>,
+	/// Color of the pipes in images as #rrggbb or #rrggbbaa
+	#[arg(long, value_parser = render::parse_color)]
+	foreground: Option<[u8; 4]>,
+	/// PNG image with a sprite for every tile to draw images with, laid out
+	/// left to right and top to bottom in the order of the tileset
+	#[arg(long, conflicts_with = "sample")]
+	atlas: Option<PathBuf>,
+	/// Width and height of the sprites in the atlas, the cell size by default
+	#[arg(long, requires = "atlas", value_parser = parse_size)]
+	atlas_tile_size: Option<usize>,
+	/// How long every frame of a GIF is shown, in milliseconds
+	#[arg(long, default_value_t = 50)]
+	frame_delay: u64,
+	/// Collapses to leave out between two frames of a GIF
+	#[arg(long, default_value_t = 0)]
+	frame_skip: usize,
+	/// Draw every collapse to the terminal while generating
+	#[arg(long)]
+	animate: bool,
+	/// How long every collapse is shown with --animate, in milliseconds
+	#[arg(long, default_value_t = 50, requires = "animate")]
+	animation_delay: u64,
+	/// File to write the map to instead of stdout
+	#[arg(short, long)]
+	output: Option<PathBuf>,
+}
 
-#[derive(Clone, Debug)]
-struct InvalidDomainError;
-impl Display for InvalidDomainError {
-	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-		write!(f, "Domain has an invalid state")
+/// Reads a size, which has to be at least 1.
+fn parse_size(s: &str) -> Result<usize, String> {
+	match s.parse() {
+		Ok(0) => Err("Sizes start at 1".to_string()),
+		Ok(size) => Ok(size),
+		Err(_) => Err(format!("{} is not a size", s)),
 	}
 }
 
-#[derive(Debug)]
-enum Domain {
-	Collapsed(State),
-	Superposition(Vec<State>),
-	Invalid,
+/// Why `main` failed, each reason with its own exit code.
+enum Failure {
+	Generation(GenerationError),
+	Input(String),
+	Output(std::io::Error),
 }
-impl Domain {
-	fn enthropy(&self) -> Result<usize, InvalidDomainError> {
-		use Domain::*;
+impl Failure {
+	fn exit_code(&self) -> ExitCode {
+		use Failure::*;
 
 		match self {
-			Invalid => Err(InvalidDomainError),
-			Collapsed(_) => Ok(0),
-			Superposition(v) => Ok(v.len()),
+			Generation(_) => ExitCode::from(1),
+			Input(_) => ExitCode::from(3),
+			Output(_) => ExitCode::from(4),
 		}
 	}
-	fn collapse(&mut self) {
-		use Domain::*;
+}
+impl Display for Failure {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		use Failure::*;
 
 		match self {
-			Collapsed(_) => {},
-			Superposition(v) => {
-				*self = match v.choose_mut(&mut rand::thread_rng()) {
-					Some(s) => Collapsed(*s),
-					None => Invalid,
-				};
-			},
-			Invalid => {},
+			Generation(e) => write!(f, "Generation failed: {}", e),
+			Input(e) => write!(f, "{}", e),
+			Output(e) => write!(f, "Could not write output: {}", e),
 		}
-
 	}
 }
-impl Default for Domain {
-	fn default() -> Self {
-		Domain::Superposition(State::all())
+
+fn main() -> ExitCode {
+
+	let args = Args::parse();
+	// patterns are written as blocks colored with escape codes only a terminal understands
+	if args.sample.is_some() && matches!(args.format, Format::Text) && (args.output.is_some() || !std::io::stdout().is_terminal()) {
+		Args::command()
+			.error(ErrorKind::ArgumentConflict, "Maps made from a sample can only be written as text to a terminal, use --format png or --format svg")
+			.exit();
 	}
-}
-impl ToString for Domain {
-	fn to_string(&self) -> String {
-		use Domain::*;
 
-		match self {
-			Collapsed(s) => s.to_string(),
-			Superposition(v) => v.len().to_string(),
-			Invalid => "!".to_string(),
-		}
+	match run(&args) {
+		Ok(()) => ExitCode::SUCCESS,
+		Err(failure) => {
+			eprintln!("{}", failure);
+			failure.exit_code()
+		},
 	}
-}
 
+}
 
+fn run(args: &Args) -> Result<(), Failure> {
+	if let Some(path) = &args.sample {
+		let patterns = overlapping::load(path, args.pattern_size, args.rotations, args.reflections)
+			.map_err(|e| Failure::Input(e.to_string()))?;
+		return generate(patterns, args, None, vec![]);
+	}
 
-#[derive(Debug, Clone)]
-struct OutOfBoundsError;
-impl Display for OutOfBoundsError {
-	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-		write!(f, "Index out of bounds")
+	match &args.tileset {
+		Some(path) => {
+			let tileset = Tileset::load(path).map_err(|e| Failure::Input(e.to_string()))?;
+			generate_tiled(tileset.tiles, args)
+		},
+		None => generate_tiled(State::all().to_vec(), args),
 	}
 }
 
-struct Field {
-	size: usize,
-	domains: Vec<Domain>,
-}
-impl Field {
-	fn new(size: usize) -> Self {
-		Self {
-			size,
-			domains: (0..size*size).map(|_| Domain::default()).collect(),
-		}
+/// Generates with `tiles`, or with the rules learned from the example map if
+/// one is given.
+fn generate_tiled<T: Pipe + Display>(tiles: Vec<T>, args: &Args) -> Result<(), Failure> {
+	let atlas = match &args.atlas {
+		Some(path) => {
+			let tile_size = args.atlas_tile_size.unwrap_or(args.cell_size);
+			let names = tiles.iter().map(Tile::name).collect();
+			let atlas = Atlas::load(path, tile_size, names)
+				.map_err(|e| Failure::Input(format!("Could not read atlas: {}", e)))?;
+			Some(atlas)
+		},
+		None => None,
+	};
+
+	match &args.example {
+		Some(path) => {
+			let learned = learn::load(path, &tiles).map_err(|e| Failure::Input(e.to_string()))?;
+			let constraints = pipe_constraints(&learned, args)?;
+			generate(learned, args, atlas.as_ref(), constraints)
+		},
+		None => {
+			let constraints = pipe_constraints(&tiles, args)?;
+			generate(tiles, args, atlas.as_ref(), constraints)
+		},
 	}
-	fn get(&self, x: usize, y: usize) -> Result<&Domain, OutOfBoundsError> {
-		if x < self.size && y < self.size {
-			self.domains.get(y * self.size + x).ok_or(OutOfBoundsError)
-		} else {
-			Err(OutOfBoundsError)
+}
+
+/// The constraints on the pipe network asked for by the arguments.
+fn pipe_constraints<T: Pipe>(tiles: &[T], args: &Args) -> Result<Vec<Box<dyn Constraint<T>>>, Failure> {
+	let mut constraints: Vec<Box<dyn Constraint<T>>> = vec![];
+	if args.connected {
+		if !tiles.iter().any(|tile| Direction::all().into_iter().any(|direction| tile.connects(direction))) {
+			return Err(Failure::Input("--connected needs tiles with pipes".to_string()));
 		}
+		constraints.push(Box::new(Connected::new(tiles)));
 	}
-	fn get_mut(&mut self, x: usize, y: usize) -> Result<&mut Domain, OutOfBoundsError> {
-		if x < self.size && y < self.size {
-			self.domains.get_mut(y * self.size + x).ok_or(OutOfBoundsError)
-		} else {
-			Err(OutOfBoundsError)
+	for route in &args.path {
+		for (x, y, z) in [route.from, route.to] {
+			if x >= args.width || y >= args.height || z >= args.depth {
+				Args::command()
+					.error(ErrorKind::ValueValidation, format!("Cell ({}, {}, {}) of the path is outside the field", x, y, z))
+					.exit();
+			}
 		}
+		constraints.push(Box::new(Path::new(tiles, route.from, route.to)));
 	}
-	fn collapse_random(&mut self) -> Result<bool, InvalidDomainError> {
 
-		let mut minimum_enthropy = State::count() + 1;
-		let mut minimum_enthropy_domains: Vec<&mut Domain> = vec![];
+	Ok(constraints)
+}
 
-		for domain in self.domains.iter_mut() {
-			let enthropy = domain.enthropy()?;
-			if enthropy == 0 {
-				continue;
-			}
+/// Makes up to `args.attempts` attempts at generating a map and writes the
+/// first one that succeeds.
+fn generate<T: Tile + Display + Draw + Vector>(tiles: Vec<T>, args: &Args, atlas: Option<&Atlas>, mut constraints: Vec<Box<dyn Constraint<T>>>) -> Result<(), Failure> {
+	let first_seed = args.seed.unwrap_or_else(rand::random);
+
+	let cells = args.width * args.height * args.depth;
+	for count in &args.count {
+		let tile = tiles
+			.iter()
+			.position(|tile| tile.name() == count.tile)
+			.ok_or(Failure::Input(UnknownTileError(count.tile.clone()).to_string()))?;
+		let (min, max) = count.range(cells);
+		if min > max.min(cells) {
+			Args::command()
+				.error(ErrorKind::ValueValidation, format!("Tile {} can not fill at least {} and at most {} of {} cells", count.tile, min, max, cells))
+				.exit();
+		}
+		constraints.push(Box::new(Count::new(tile, min, max)));
+	}
 
-			if enthropy < minimum_enthropy {
-				minimum_enthropy_domains.clear();
-				minimum_enthropy = enthropy
+	let mut result = Err(GenerationError::Contradiction(None));
+	for attempt in 0..args.attempts.max(1) {
+		let seed = first_seed.wrapping_add(attempt);
+		let mut rng = ChaCha8Rng::seed_from_u64(seed);
+
+		let mut f = Field::new(tiles.clone(), args.width, args.height, args.depth, args.boundary.clone())
+			.map_err(|e| Failure::Input(e.to_string()))?;
+		for pin in &args.pin {
+			let (x, y, z) = pin.cell;
+			let result = match &pin.tiles[..] {
+				[tile] => f.pin(x, y, z, tile),
+				tiles => f.restrict(x, y, z, &tiles.iter().map(String::as_str).collect::<Vec<&str>>()),
+			};
+			result.map_err(|e| Failure::Input(e.to_string()))?;
+		}
+		let mut recorder = match args.format {
+			Format::Gif => Some(Recorder::new(style(args), atlas, args.frame_delay, args.frame_skip)),
+			_ => None,
+		};
+		let mut animation = match args.animate {
+			true => Some(Animation::new(Duration::from_millis(args.animation_delay))),
+			false => None,
+		};
+		result = f.generate(&mut rng, &mut constraints, args.backtracks, |field, events| {
+			if let Some(recorder) = &mut recorder {
+				recorder.record(field);
 			}
-			if enthropy == minimum_enthropy {
-				minimum_enthropy_domains.push(domain);
+			if let Some(animation) = &mut animation {
+				animation.show(field, events);
 			}
-
-		}
-
-		match minimum_enthropy_domains.choose_mut(&mut rand::thread_rng()) {
-			Some(d) => {
-				d.collapse();
-				Ok(true)
+		});
+		drop(animation);
+
+		match &result {
+			Ok(()) => {
+				// a map made from a seed nobody chose can only be made again with it
+				if args.seed != Some(seed) {
+					eprintln!("Generated with seed {}", seed);
+				}
+				return write_output(&f, args, atlas, recorder).map_err(Failure::Output);
 			},
-			None => Ok(false),
+			Err(e) => eprintln!("Attempt {} failed, seed: {}, {}", attempt + 1, seed, e),
 		}
-
 	}
-	fn propagate(&mut self) -> Result<(), InvalidDomainError> {
-		use Domain::*;
-
-		fn is_allowed_state(state: &State, left: Option<&Domain>, right: Option<&Domain>, top: Option<&Domain>, bottom: Option<&Domain>) -> Result<bool, InvalidDomainError> {
-			Ok(
-				match left {
-					Some(Invalid) => return Err(InvalidDomainError),
-					Some(Collapsed(other)) => state.fits_left(other),
-					Some(Superposition(v)) => v.iter().any(|other| state.fits_left(other)),
-					None => true,
-				} &&
-				match right {
-					Some(Invalid) => return Err(InvalidDomainError),
-					Some(Collapsed(other)) => state.fits_right(other),
-					Some(Superposition(v)) => v.iter().any(|other| state.fits_right(other)),
-					None => true,
-				} &&
-				match bottom {
-					Some(Invalid) => return Err(InvalidDomainError),
-					Some(Collapsed(other)) => state.fits_bottom(other),
-					Some(Superposition(v)) => v.iter().any(|other| state.fits_bottom(other)),
-					None => true,
-				} &&
-				match top {
-					Some(Invalid) => return Err(InvalidDomainError),
-					Some(Collapsed(other)) => state.fits_top(other),
-					Some(Superposition(v)) => v.iter().any(|other| state.fits_top(other)),
-					None => true,
-				}
-			)
-		}
-
-		for y in 0..self.size {
-			for x in 0..self.size {
-				let current = self.get(x, y).unwrap();
-				let left = match x {
-					0 => None,
-					_ => self.get(x - 1, y).ok(),
-				};
-				let right = self.get(x + 1, y).ok();
-				let top = match y {
-					0 => None,
-					_ => self.get(x, y - 1).ok(),
-				};
-				let bottom = self.get(x, y + 1).ok();
-
-				match current {
-					Invalid => return Err(InvalidDomainError),
-					Collapsed(state) => {
-						if !is_allowed_state(state, left, right, top, bottom)? {
-							*self.get_mut(x, y).unwrap() = Invalid;
-						}
-					},
-					Superposition(v) => {
-						let mut allowed_states: Vec<State> = vec![];
-
-						for state in v {
-							if is_allowed_state(state, left, right, top, bottom)? {
-								allowed_states.push(*state);
-							}
-						}
-						
-						*self.get_mut(x, y).unwrap() = match allowed_states.len() {
-							0 => Invalid,
-							1 => Collapsed(allowed_states[0]),
-							_ => Superposition(allowed_states),
-						}
-					}
-				}
-			}
-		}
 
-		Ok(())
-	}
+	result.map_err(Failure::Generation)
 }
-impl Display for Field {
-	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-		let mut out = String::new();
-		for y in 0..self.size {
-			for x in 0..self.size {
-				out = format!("{}{}", out, self.get(x, y).unwrap().to_string());
-			}
-			out += "\n";
-		}
 
-		write!(f, "{}", out)
+/// Style of images as set by the arguments.
+fn style(args: &Args) -> Style {
+	let default = Style::default();
+
+	Style {
+		cell_size: args.cell_size,
+		line_width: args.line_width.unwrap_or((args.cell_size / 4).max(1)),
+		background: args.background.unwrap_or(default.background),
+		foreground: args.foreground.unwrap_or(default.foreground),
 	}
 }
 
-fn main() -> Result<(), InvalidDomainError> {
-
-	let mut f = Field::new(15);
-	
-	while f.domains.iter().any(|d| matches!(d, Domain::Superposition(_))) && !f.domains.iter().any(|d| matches!(d, Domain::Invalid)) {
-
-		if f.collapse_random().is_err() {
-			println!("{}", f);
-			break;
-		}
-
-		let mut old_enthropy = 0;
-		let mut new_enthropy = 1;
-		while old_enthropy != new_enthropy {
-			if f.propagate().is_err() {
-				break;
-			};
-			old_enthropy = new_enthropy;
-			new_enthropy = f.domains
-				.iter()
-				.map(|d| d.enthropy())
-				.collect::<Result<Vec<usize>, InvalidDomainError>>()?
-				.iter()
-				.sum();
-		}
-		
+/// Writes the field in the chosen format, taking the animation from the
+/// recorder if the format is a GIF.
+fn write_output<T: Tile + Display + Draw + Vector>(field: &Field<T>, args: &Args, atlas: Option<&Atlas>, recorder: Option<Recorder>) -> std::io::Result<()> {
+	let mut out: Box<dyn Write> = match &args.output {
+		Some(path) => Box::new(std::fs::File::create(path)?),
+		None => Box::new(std::io::stdout().lock()),
+	};
+
+	match (args.format, recorder) {
+		(Format::Text, _) => write!(out, "{}", field)?,
+		(Format::Png, _) => render::render(field, &style(args), atlas).write_png(&mut out)?,
+		(Format::Svg, _) => svg::write_svg(field, &style(args), &mut out)?,
+		(Format::Gif, recorder) => {
+			let recorder = recorder.unwrap_or_else(|| Recorder::new(style(args), atlas, args.frame_delay, args.frame_skip));
+			out.write_all(&recorder.finish(field)?)?;
+		},
 	}
 
-	println!("{}", f);
-
-	Ok(())
-
+	out.flush()
 }
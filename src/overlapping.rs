@@ -0,0 +1,149 @@
+use std::{collections::HashMap, fmt::Display, path::Path};
+
+use crate::{image::{Image, ImageError}, render::{Draw, Style}, state_set::MAX_STATES, Direction, Tile};
+
+#[derive(Debug)]
+pub enum SampleError {
+	Image(ImageError),
+	TooSmall(usize),
+	TooManyPatterns(usize),
+}
+impl Display for SampleError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		use SampleError::*;
+
+		match self {
+			Image(e) => write!(f, "Could not read sample: {}", e),
+			TooSmall(size) => write!(f, "Sample is smaller than the {0}x{0} patterns", size),
+			TooManyPatterns(count) => write!(f, "Sample has {} distinct patterns, at most {} are supported", count, MAX_STATES),
+		}
+	}
+}
+
+/// A `size`×`size` block of pixels seen in a `Sample`.
+///
+/// Cells of a field filled with patterns overlap: a pattern fits next to
+/// another when both agree on every pixel they share once shifted by one.
+#[derive(Clone, Debug)]
+pub struct Pattern {
+	id: usize,
+	size: usize,
+	pixels: Vec<[u8; 4]>,
+	count: usize,
+}
+impl Pattern {
+	fn pixel(&self, x: usize, y: usize) -> [u8; 4] {
+		self.pixels[y * self.size + x]
+	}
+	/// Color of the cell the pattern is placed in, its top left pixel.
+	pub fn color(&self) -> [u8; 4] {
+		self.pixels[0]
+	}
+}
+impl Tile for Pattern {
+	fn name(&self) -> String {
+		format!("pattern{}", self.id)
+	}
+	fn weight(&self) -> usize {
+		self.count
+	}
+	fn fits(&self, other: &Self, direction: Direction) -> bool {
+		use Direction::*;
+
+		let n = self.size;
+		let (dx, dy) = match direction {
+			Left => (-1, 0),
+			Right => (1, 0),
+			Top => (0, -1),
+			Bottom => (0, 1),
+			// patterns come from a flat image, so levels are independent
+			Up | Down => return true,
+		};
+
+		// every pixel of `self` that `other` also covers once moved by (dx, dy)
+		(0..n).all(|y| (0..n).all(|x| {
+			let (ox, oy) = (x as isize - dx, y as isize - dy);
+			ox < 0 || oy < 0 || ox >= n as isize || oy >= n as isize
+				|| self.pixel(x, y) == other.pixel(ox as usize, oy as usize)
+		}))
+	}
+}
+impl Draw for Pattern {
+	fn draw(&self, image: &mut Image, x: usize, y: usize, style: &Style) {
+		image.fill(x, y, style.cell_size, style.cell_size, self.color());
+	}
+}
+impl Display for Pattern {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		// a block of the cell's color
+		let [r, g, b, _] = self.color();
+		write!(f, "\x1b[48;2;{};{};{}m  \x1b[0m", r, g, b)
+	}
+}
+
+/// The `size`×`size` block of pixels turned a quarter clockwise.
+fn rotate(pixels: &[[u8; 4]], size: usize) -> Vec<[u8; 4]> {
+	(0..size*size).map(|i| pixels[(size - 1 - i % size) * size + i / size]).collect()
+}
+/// The `size`×`size` block of pixels mirrored left to right.
+fn reflect(pixels: &[[u8; 4]], size: usize) -> Vec<[u8; 4]> {
+	(0..size*size).map(|i| pixels[i / size * size + size - 1 - i % size]).collect()
+}
+
+/// Extracts every distinct `size`×`size` pattern of the sample, counting how
+/// often each one occurs. With `rotations` and `reflections` the rotated and
+/// mirrored versions of every block are counted as well.
+pub fn patterns(sample: &Image, size: usize, rotations: bool, reflections: bool) -> Result<Vec<Pattern>, SampleError> {
+	if size == 0 || sample.width < size || sample.height < size {
+		return Err(SampleError::TooSmall(size));
+	}
+
+	let mut patterns: Vec<Pattern> = vec![];
+	let mut ids: HashMap<Vec<[u8; 4]>, usize> = HashMap::new();
+	let mut add = |pixels: Vec<[u8; 4]>| {
+		match ids.get(&pixels) {
+			Some(id) => patterns[*id].count += 1,
+			None => {
+				ids.insert(pixels.clone(), patterns.len());
+				patterns.push(Pattern { id: patterns.len(), size, pixels, count: 1 });
+			},
+		}
+	};
+
+	for y in 0..=sample.height - size {
+		for x in 0..=sample.width - size {
+			let mut variants: Vec<Vec<[u8; 4]>> = vec![
+				(0..size*size)
+					.map(|i| sample.get(x + i % size, y + i / size))
+					.collect(),
+			];
+			if rotations {
+				for i in 0..3 {
+					variants.push(rotate(&variants[i], size));
+				}
+			}
+			if reflections {
+				for i in 0..variants.len() {
+					variants.push(reflect(&variants[i], size));
+				}
+			}
+
+			for variant in variants {
+				add(variant);
+			}
+		}
+	}
+
+	if patterns.len() > MAX_STATES {
+		return Err(SampleError::TooManyPatterns(patterns.len()));
+	}
+
+	Ok(patterns)
+}
+
+/// Like `patterns`, reading the sample from a PNG file.
+pub fn load(path: impl AsRef<Path>, size: usize, rotations: bool, reflections: bool) -> Result<Vec<Pattern>, SampleError> {
+	let sample = Image::load(path).map_err(SampleError::Image)?;
+
+	patterns(&sample, size, rotations, reflections)
+}
@@ -0,0 +1,171 @@
+use std::path::Path;
+
+use crate::{image::{Image, ImageError}, Direction, Domain, Field, Pipe, Tile};
+
+/// Colors and sizes of rendered fields.
+#[derive(Clone, Debug)]
+pub struct Style {
+	/// Width and height of a cell in pixels.
+	pub cell_size: usize,
+	/// Width of the pipes in pixels.
+	pub line_width: usize,
+	pub background: [u8; 4],
+	pub foreground: [u8; 4],
+}
+impl Default for Style {
+	fn default() -> Self {
+		Self {
+			cell_size: 16,
+			line_width: 4,
+			background: [24, 24, 32, 255],
+			foreground: [220, 220, 230, 255],
+		}
+	}
+}
+
+/// Parses a color written as `#rrggbb` or `#rrggbbaa`.
+pub fn parse_color(text: &str) -> Result<[u8; 4], String> {
+	let digits = text.strip_prefix('#').unwrap_or(text);
+	let channel = |i: usize| {
+		digits
+			.get(i * 2..i * 2 + 2)
+			.and_then(|hex| u8::from_str_radix(hex, 16).ok())
+			.ok_or(format!("{} is not a color like #rrggbb or #rrggbbaa", text))
+	};
+
+	match digits.len() {
+		6 => Ok([channel(0)?, channel(1)?, channel(2)?, 255]),
+		8 => Ok([channel(0)?, channel(1)?, channel(2)?, channel(3)?]),
+		_ => Err(format!("{} is not a color like #rrggbb or #rrggbbaa", text)),
+	}
+}
+
+/// A tile that can be drawn into an image.
+pub trait Draw {
+	/// Draws the tile into the cell whose top left pixel is at (`x`, `y`).
+	fn draw(&self, image: &mut Image, x: usize, y: usize, style: &Style);
+}
+impl<T: Pipe> Draw for T {
+	/// Draws a line from the center of the cell to every connected edge. Pipes
+	/// to other levels only show as the square at the center.
+	fn draw(&self, image: &mut Image, x: usize, y: usize, style: &Style) {
+		use Direction::*;
+
+		let size = style.cell_size;
+		let width = style.line_width.min(size);
+		let start = (size - width) / 2;
+		let end = start + width;
+
+		if Direction::all().iter().any(|direction| self.connects(*direction)) {
+			image.fill(x + start, y + start, width, width, style.foreground);
+		}
+		for direction in Direction::all().into_iter().filter(|direction| self.connects(*direction)) {
+			let (left, top, right, bottom) = match direction {
+				Left => (0, start, start, end),
+				Right => (end, start, size, end),
+				Top => (start, 0, end, start),
+				Bottom => (start, end, end, size),
+				Up | Down => continue,
+			};
+			image.fill(x + left, y + top, right - left, bottom - top, style.foreground);
+		}
+	}
+}
+
+/// Sprites for tiles, cut from an image holding `tile_size`×`tile_size`
+/// squares left to right and top to bottom, one for each tile in `names`.
+pub struct Atlas {
+	image: Image,
+	tile_size: usize,
+	names: Vec<String>,
+}
+impl Atlas {
+	pub fn load(path: impl AsRef<Path>, tile_size: usize, names: Vec<String>) -> Result<Self, ImageError> {
+		Ok(Self {
+			image: Image::load(path)?,
+			tile_size,
+			names,
+		})
+	}
+	/// Top left pixel of the sprite of the named tile, if the atlas has one.
+	fn sprite(&self, name: &str) -> Option<(usize, usize)> {
+		let columns = self.image.width / self.tile_size.max(1);
+		let index = self.names.iter().position(|n| n == name)?;
+		let (x, y) = (index % columns.max(1) * self.tile_size, index / columns.max(1) * self.tile_size);
+
+		match x + self.tile_size <= self.image.width && y + self.tile_size <= self.image.height {
+			true => Some((x, y)),
+			false => None,
+		}
+	}
+}
+
+/// Rows of cells the levels of the field take up when drawn one under another,
+/// an empty row apart.
+pub fn rows<T: Tile>(field: &Field<T>) -> usize {
+	(field.height * field.depth + field.depth).saturating_sub(1)
+}
+/// Column and row of the cell with the given index when the levels are laid
+/// out like `rows` counts them.
+pub fn cell<T: Tile>(field: &Field<T>, index: usize) -> (usize, usize) {
+	let (x, y, z) = field.position(index);
+
+	(x, z * (field.height + 1) + y)
+}
+
+/// Color `amount` of the way from `from` to `to`.
+fn mix(from: [u8; 4], to: [u8; 4], amount: f64) -> [u8; 4] {
+	std::array::from_fn(|i| (from[i] as f64 + (to[i] as f64 - from[i] as f64) * amount).round() as u8)
+}
+
+/// Renders the collapsed cells of the field, taking sprites from `atlas` where
+/// it has one and drawing the tiles otherwise. Cells are as large as the
+/// atlas' sprites if there is an atlas and `style.cell_size` if not.
+///
+/// Cells that are not collapsed yet are shaded by their enthropy, lighter the
+/// fewer tiles they have left, and cells without any are red. The levels of a
+/// 3D field are drawn from top to bottom, one empty row of cells apart.
+pub fn render<T: Tile + Draw>(field: &Field<T>, style: &Style, atlas: Option<&Atlas>) -> Image {
+	const INVALID: [u8; 4] = [200, 40, 40, 255];
+
+	let style = Style {
+		cell_size: atlas.map_or(style.cell_size, |atlas| atlas.tile_size),
+		..style.clone()
+	};
+	let size = style.cell_size;
+	let mut image = Image::new(field.width * size, rows(field) * size, style.background);
+	let max_enthropy = Domain::new(&field.rules).enthropy().unwrap_or_default();
+
+	for (index, domain) in field.domains.iter().enumerate() {
+		let (x, y) = cell(field, index);
+		let (x, y) = (x * size, y * size);
+		let tile = match domain {
+			Domain::Collapsed(tile) => &field.tiles[*tile],
+			Domain::Superposition(candidates) => {
+				let certainty = match max_enthropy > 0.0 {
+					true => 1.0 - candidates.enthropy() / max_enthropy,
+					false => 1.0,
+				};
+				image.fill(x, y, size, size, mix(style.background, style.foreground, 0.1 + 0.4 * certainty));
+				continue;
+			},
+			Domain::Invalid => {
+				image.fill(x, y, size, size, INVALID);
+				continue;
+			},
+		};
+
+		match atlas.and_then(|atlas| atlas.sprite(&tile.name()).map(|sprite| (atlas, sprite))) {
+			Some((atlas, (sprite_x, sprite_y))) => {
+				for dy in 0..size {
+					for dx in 0..size {
+						image.pixels[(y + dy) * image.width + x + dx] = atlas.image.get(sprite_x + dx, sprite_y + dy);
+					}
+				}
+			},
+			None => tile.draw(&mut image, x, y, &style),
+		}
+	}
+
+	image
+}
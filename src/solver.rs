@@ -0,0 +1,278 @@
+use std::collections::HashMap;
+
+use rand::RngCore;
+
+use crate::{constraint::{Constraint, Violation}, state_set::StateSet, Domain, Field, GenerationError, Tile};
+
+/// Something that happened to a field during a step of a `Solver`. Cells are
+/// given by their index, `(z * height + y) * width + x`, and tiles by their
+/// index in the field's tiles.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Event {
+	/// The cell was collapsed to the tile.
+	Observed { index: usize, tile: usize },
+	/// The tiles were removed from the candidates of the cell.
+	Removed { index: usize, tiles: StateSet },
+	/// The cell has no candidates left.
+	Contradiction { index: usize },
+	/// Everything since the cell was collapsed to the tile was undone and the
+	/// tile banned from it.
+	Backtracked { index: usize, tile: usize },
+}
+
+/// A collapse made by a `Solver`, remembered so it can be undone.
+struct Decision {
+	index: usize,
+	state: usize,
+	trail_length: usize,
+}
+
+/// Fills a field one collapse at a time.
+///
+/// After every propagation the constraints are checked, and a field failing
+/// one is treated like a contradiction. Constraints are told which cells
+/// changed since they last looked, the first time every cell.
+///
+/// When a contradiction comes up the field is restored to how it was before
+/// the latest collapse, the state chosen there is banned and solving carries
+/// on. After `max_backtracks` such rewinds it gives up; with a limit of `0`
+/// the first contradiction is returned right away.
+pub struct Solver<'a, T: Tile, R: RngCore> {
+	field: &'a mut Field<T>,
+	rng: &'a mut R,
+	constraints: &'a mut [Box<dyn Constraint<T>>],
+	max_backtracks: usize,
+	backtracks: usize,
+	decisions: Vec<Decision>,
+	started: bool,
+	events: Vec<Event>,
+	/// Length of the trail when the constraints last looked at the field.
+	seen: usize,
+	/// Cells the constraints have to be told about besides the ones on the
+	/// trail after `seen`, since undoing took them off it.
+	changed: Vec<usize>,
+	/// The constraint that failed the latest propagation, if one did.
+	violation: Option<Violation>,
+}
+impl<'a, T: Tile, R: RngCore> Solver<'a, T, R> {
+	/// Resets the constraints, so they can be used again for another field.
+	pub fn new(field: &'a mut Field<T>, rng: &'a mut R, constraints: &'a mut [Box<dyn Constraint<T>>], max_backtracks: usize) -> Self {
+		for constraint in constraints.iter_mut() {
+			constraint.reset();
+		}
+
+		Self {
+			seen: field.trail.len(),
+			changed: (0..field.domains.len()).collect(),
+			field,
+			rng,
+			constraints,
+			max_backtracks,
+			backtracks: 0,
+			decisions: vec![],
+			started: false,
+			events: vec![],
+			violation: None,
+		}
+	}
+	pub fn field(&self) -> &Field<T> {
+		self.field
+	}
+	/// What happened during the latest step, in order. Also filled when the
+	/// step failed.
+	pub fn events(&self) -> &[Event] {
+		&self.events
+	}
+	/// Makes a step and returns whether there was one to make.
+	///
+	/// The first step only propagates what the field was set up with. Every
+	/// step after that collapses the domain with the lowest enthropy and
+	/// propagates, backtracking from contradictions as needed.
+	pub fn step(&mut self) -> Result<bool, GenerationError> {
+		self.events.clear();
+
+		if !self.started {
+			self.started = true;
+			let mark = self.field.trail.len();
+			let result = self.propagate();
+			self.removals(mark);
+			if let Err(index) = result {
+				self.events.push(Event::Contradiction { index });
+				return Err(GenerationError::Contradiction(self.violation.take()));
+			}
+
+			return Ok(true);
+		}
+
+		let trail_length = self.field.trail.len();
+		let index = match self.field.collapse_random(self.rng)? {
+			Some(index) => index,
+			None => return Ok(false),
+		};
+		if let Domain::Collapsed(state) = self.field.domains[index] {
+			self.decisions.push(Decision { index, state, trail_length });
+			self.events.push(Event::Observed { index, tile: state });
+		}
+
+		let mut mark = self.field.trail.len();
+		let mut result = self.propagate();
+		while let Err(index) = result {
+			self.removals(mark);
+			self.events.push(Event::Contradiction { index });
+
+			if self.max_backtracks == 0 {
+				return Err(GenerationError::Contradiction(self.violation.take()));
+			}
+			if self.backtracks == self.max_backtracks {
+				return Err(GenerationError::BacktrackLimitReached(self.max_backtracks, self.violation.take()));
+			}
+			self.backtracks += 1;
+
+			let Some(decision) = self.decisions.pop() else {
+				return Err(GenerationError::Contradiction(self.violation.take()));
+			};
+			self.undo(decision.trail_length);
+			self.events.push(Event::Backtracked { index: decision.index, tile: decision.state });
+
+			mark = self.field.trail.len();
+			let mut domain = self.field.domains[decision.index];
+			domain.ban(decision.state, &self.field.rules);
+			self.field.set(decision.index, domain);
+			result = self.propagate();
+		}
+		self.removals(mark);
+
+		Ok(true)
+	}
+	/// Propagates and applies what the constraints allow until neither
+	/// changes the field any more. Returns the cell of a contradiction if
+	/// there is one, keeping the violation if a constraint failed.
+	fn propagate(&mut self) -> Result<(), usize> {
+		self.violation = None;
+		loop {
+			if self.field.propagate().is_err() {
+				return Err(self.field.invalid().unwrap_or_default());
+			}
+
+			let mut changed = std::mem::take(&mut self.changed);
+			changed.extend(self.field.trail[self.seen..].iter().map(|(index, _)| *index));
+			self.seen = self.field.trail.len();
+
+			let mut restricted = false;
+			for constraint in self.constraints.iter_mut() {
+				let allowed = match constraint.check(self.field, &changed) {
+					Ok(allowed) => allowed,
+					Err(violation) => {
+						// the constraints after this one haven't seen the changes yet
+						self.changed = changed;
+						let index = violation.index;
+						self.violation = Some(violation);
+						return Err(index);
+					},
+				};
+				for (index, allowed) in allowed {
+					let mut domain = self.field.domains[index];
+					if domain.restrict(allowed, &self.field.rules) {
+						self.field.set(index, domain);
+						restricted = true;
+					}
+				}
+			}
+			if !restricted {
+				return Ok(());
+			}
+		}
+	}
+	/// Undoes the field back to when the trail had the given length, keeping
+	/// the cells that changed for the constraints.
+	fn undo(&mut self, trail_length: usize) {
+		self.changed.extend(self.field.trail[trail_length..].iter().map(|(index, _)| *index));
+		self.seen = self.seen.min(trail_length);
+		self.field.undo(trail_length);
+	}
+	/// Adds a `Removed` event for every change on the trail since it had the
+	/// length `mark`.
+	fn removals(&mut self, mark: usize) {
+		// the states of each domain after the change, going back from the current ones
+		let mut after: HashMap<usize, StateSet> = HashMap::new();
+		let mut removals = vec![];
+		for (index, before) in self.field.trail[mark..].iter().rev() {
+			let states = after.get(index).copied().unwrap_or_else(|| self.field.domains[*index].states());
+			removals.push(Event::Removed { index: *index, tiles: before.states() - states });
+			after.insert(*index, before.states());
+		}
+
+		self.events.extend(removals.into_iter().rev());
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use rand_chacha::{rand_core::SeedableRng, ChaCha8Rng};
+
+	use super::*;
+	use crate::{constraint::Violation, Boundary, Direction};
+
+	/// Two colours that never fit next to themselves.
+	#[derive(Clone, Debug)]
+	struct Colour(usize);
+	impl Tile for Colour {
+		fn name(&self) -> String {
+			self.0.to_string()
+		}
+		fn weight(&self) -> usize {
+			1
+		}
+		fn fits(&self, other: &Self, _: Direction) -> bool {
+			self.0 != other.0
+		}
+	}
+
+	/// Fails at the first cell to change once any cell is collapsed, the
+	/// first time only.
+	struct RejectFirst(bool);
+	impl Constraint<Colour> for RejectFirst {
+		fn check(&mut self, field: &Field<Colour>, changed: &[usize]) -> Result<Vec<(usize, StateSet)>, Violation> {
+			let collapsed = field.domains.iter().any(|domain| matches!(domain, Domain::Collapsed(_)));
+			match changed.first() {
+				Some(&index) if collapsed && !self.0 => {
+					self.0 = true;
+					Err(Violation { index, reason: "first collapse".to_string() })
+				},
+				_ => Ok(vec![]),
+			}
+		}
+	}
+
+	#[test]
+	fn backtracks_in_order() {
+		let mut field = Field::new(vec![Colour(0), Colour(1)], 2, 1, 1, Boundary::Open).unwrap();
+		let mut rng = ChaCha8Rng::seed_from_u64(0);
+		let mut constraints: Vec<Box<dyn Constraint<Colour>>> = vec![Box::new(RejectFirst(false))];
+		let mut solver = Solver::new(&mut field, &mut rng, &mut constraints, 1);
+
+		assert!(solver.step().unwrap());
+		assert!(solver.events().is_empty());
+
+		assert!(solver.step().unwrap());
+		let Event::Observed { index, tile } = solver.events()[0] else {
+			panic!("{:?} is not an observation", solver.events()[0]);
+		};
+		let (other_index, other_tile) = (1 - index, 1 - tile);
+		assert_eq!(
+			solver.events(),
+			[
+				Event::Observed { index, tile },
+				Event::Removed { index: other_index, tiles: StateSet::single(tile) },
+				Event::Contradiction { index },
+				Event::Backtracked { index, tile },
+				Event::Removed { index, tiles: StateSet::single(tile) },
+				Event::Removed { index: other_index, tiles: StateSet::single(other_tile) },
+			]
+		);
+
+		assert!(!solver.step().unwrap());
+		assert!(matches!(field.domains[index], Domain::Collapsed(state) if state == other_tile));
+		assert!(matches!(field.domains[other_index], Domain::Collapsed(state) if state == tile));
+	}
+}
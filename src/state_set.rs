@@ -0,0 +1,148 @@
+use std::ops::{BitAnd, BitOr, BitOrAssign, Sub};
+
+const WORDS: usize = 4;
+
+/// Largest number of states a `StateSet` can hold.
+pub const MAX_STATES: usize = WORDS * 64;
+
+/// Fixed size set of state indices, one bit per state.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct StateSet([u64; WORDS]);
+impl StateSet {
+	pub fn empty() -> Self {
+		Self::default()
+	}
+	/// Set of the states `0..count`.
+	pub fn full(count: usize) -> Self {
+		let mut set = Self::empty();
+		for index in 0..count {
+			set.insert(index);
+		}
+
+		set
+	}
+	pub fn single(index: usize) -> Self {
+		let mut set = Self::empty();
+		set.insert(index);
+
+		set
+	}
+	pub fn insert(&mut self, index: usize) {
+		self.0[index / 64] |= 1 << (index % 64);
+	}
+	pub fn remove(&mut self, index: usize) {
+		self.0[index / 64] &= !(1 << (index % 64));
+	}
+	pub fn contains(&self, index: usize) -> bool {
+		self.0[index / 64] & (1 << (index % 64)) != 0
+	}
+	pub fn len(&self) -> usize {
+		self.0.iter().map(|word| word.count_ones() as usize).sum()
+	}
+	pub fn is_empty(&self) -> bool {
+		self.0.iter().all(|word| *word == 0)
+	}
+	pub fn first(&self) -> Option<usize> {
+		self.iter().next()
+	}
+	pub fn iter(&self) -> Iter {
+		Iter {
+			words: self.0,
+			word: 0,
+		}
+	}
+}
+impl BitAnd for StateSet {
+	type Output = Self;
+
+	fn bitand(mut self, rhs: Self) -> Self {
+		for (word, other) in self.0.iter_mut().zip(rhs.0) {
+			*word &= other;
+		}
+
+		self
+	}
+}
+impl BitOr for StateSet {
+	type Output = Self;
+
+	fn bitor(mut self, rhs: Self) -> Self {
+		self |= rhs;
+
+		self
+	}
+}
+impl BitOrAssign for StateSet {
+	fn bitor_assign(&mut self, rhs: Self) {
+		for (word, other) in self.0.iter_mut().zip(rhs.0) {
+			*word |= other;
+		}
+	}
+}
+impl Sub for StateSet {
+	type Output = Self;
+
+	/// States of `self` that are not in `rhs`.
+	fn sub(mut self, rhs: Self) -> Self {
+		for (word, other) in self.0.iter_mut().zip(rhs.0) {
+			*word &= !other;
+		}
+
+		self
+	}
+}
+
+/// Iterator over the indices in a `StateSet`, in ascending order.
+pub struct Iter {
+	words: [u64; WORDS],
+	word: usize,
+}
+impl Iterator for Iter {
+	type Item = usize;
+
+	fn next(&mut self) -> Option<usize> {
+		while self.word < WORDS {
+			let bits = self.words[self.word];
+			if bits != 0 {
+				self.words[self.word] = bits & (bits - 1);
+				return Some(self.word * 64 + bits.trailing_zeros() as usize);
+			}
+			self.word += 1;
+		}
+
+		None
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn operations() {
+		let mut set = StateSet::single(3) | StateSet::single(70);
+		set.insert(200);
+		assert_eq!(set.len(), 3);
+		assert!(set.contains(70) && !set.contains(71));
+
+		set.remove(70);
+		assert_eq!(set.iter().collect::<Vec<_>>(), [3, 200]);
+		assert_eq!((set & StateSet::full(10)).iter().collect::<Vec<_>>(), [3]);
+		assert_eq!((set - StateSet::single(3)).first(), Some(200));
+		assert!((set - set).is_empty());
+		assert_eq!(StateSet::full(MAX_STATES).len(), MAX_STATES);
+	}
+
+	#[test]
+	fn iterates_across_words() {
+		let indices = [0, 63, 64, 127, 128, 191, 192, 255];
+		let mut set = StateSet::empty();
+		for index in indices {
+			set.insert(index);
+		}
+
+		assert_eq!(set.iter().collect::<Vec<_>>(), indices);
+		assert_eq!(StateSet::full(130).iter().collect::<Vec<_>>(), (0..130).collect::<Vec<_>>());
+		assert_eq!(StateSet::empty().iter().next(), None);
+	}
+}
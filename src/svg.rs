@@ -0,0 +1,175 @@
+use std::io::Write;
+
+use crate::{overlapping::Pattern, render::{self, Style}, Direction, Domain, Field, Pipe, Tile};
+
+/// A tile that can be drawn as vector shapes.
+pub trait Vector {
+	/// Whether a stroke runs from the center of the tile's cell to the edge in
+	/// `direction`.
+	fn connects(&self, _direction: Direction) -> bool {
+		false
+	}
+	/// Color the whole cell is filled with, if any.
+	fn fill(&self) -> Option<[u8; 4]> {
+		None
+	}
+}
+impl<T: Pipe> Vector for T {
+	fn connects(&self, direction: Direction) -> bool {
+		Pipe::connects(self, direction)
+	}
+}
+impl Vector for Pattern {
+	fn fill(&self) -> Option<[u8; 4]> {
+		Some(self.color())
+	}
+}
+
+/// `#rrggbb` of the color, followed by an `opacity` attribute named `property`
+/// if it is not opaque.
+fn paint(property: &str, color: [u8; 4]) -> String {
+	let [r, g, b, a] = color;
+
+	match a {
+		255 => format!("{}=\"#{:02x}{:02x}{:02x}\"", property, r, g, b),
+		_ => format!("{0}=\"#{1:02x}{2:02x}{3:02x}\" {0}-opacity=\"{4:.3}\"", property, r, g, b, a as f64 / 255.0),
+	}
+}
+
+/// The strokes of every collapsed cell as lines through points on a grid of
+/// half cells. Strokes meeting end to end become one line, so a pipe is drawn
+/// as a single polyline up to where it branches or ends. The second value is
+/// whether the line is closed. Strokes to other levels are left out, and the
+/// levels of a 3D field are laid out from top to bottom one cell apart.
+fn lines<T: Tile + Vector>(field: &Field<T>) -> Vec<(Vec<(usize, usize)>, bool)> {
+	use Direction::*;
+
+	let columns = field.width * 2 + 1;
+	let rows = render::rows(field) * 2 + 1;
+	let step = |point: usize, direction: Direction| match direction {
+		Left => point - 1,
+		Right => point + 1,
+		Top => point - columns,
+		Bottom => point + columns,
+		Up | Down => point,
+	};
+
+	// `edges[point][direction]` is set if a stroke leaves `point` in `direction`
+	let mut edges = vec![[false; 4]; columns * rows];
+	for (index, domain) in field.domains.iter().enumerate() {
+		let Domain::Collapsed(tile) = domain else {
+			continue;
+		};
+		let (x, y) = render::cell(field, index);
+		let center = (y * 2 + 1) * columns + x * 2 + 1;
+		for direction in Direction::planar() {
+			if field.tiles[*tile].connects(direction) {
+				edges[center][direction as usize] = true;
+				edges[step(center, direction)][direction.opposite() as usize] = true;
+			}
+		}
+	}
+	let degree = |edges: &[bool; 4]| edges.iter().filter(|edge| **edge).count();
+
+	let mut lines = vec![];
+	let mut used = vec![[false; 4]; columns * rows];
+	let mut walk = |start: usize, direction: Direction, used: &mut Vec<[bool; 4]>| {
+		let mut points = vec![start];
+		let (mut point, mut direction) = (start, direction);
+		loop {
+			used[point][direction as usize] = true;
+			point = step(point, direction);
+			used[point][direction.opposite() as usize] = true;
+			points.push(point);
+
+			let next = Direction::planar()
+				.into_iter()
+				.find(|next| edges[point][*next as usize] && !used[point][*next as usize]);
+			match next {
+				Some(next) if degree(&edges[point]) == 2 => direction = next,
+				_ => break,
+			}
+		}
+
+		let closed = point == start && points.len() > 2;
+		if closed {
+			points.pop();
+		}
+		lines.push((points, closed));
+	};
+
+	// lines between ends and branches first, what is left are loops
+	let ends = (0..edges.len()).filter(|point| degree(&edges[*point]) != 2);
+	for start in ends.chain(0..edges.len()) {
+		for direction in Direction::planar() {
+			if edges[start][direction as usize] && !used[start][direction as usize] {
+				walk(start, direction, &mut used);
+			}
+		}
+	}
+
+	lines
+		.into_iter()
+		.map(|(points, closed)| {
+			let points: Vec<(usize, usize)> = points.into_iter().map(|point| (point % columns, point / columns)).collect();
+			(straighten(points, closed), closed)
+		})
+		.collect()
+}
+
+/// Drops the points lying on a straight line between their neighbours.
+fn straighten(points: Vec<(usize, usize)>, closed: bool) -> Vec<(usize, usize)> {
+	let n = points.len();
+
+	(0..n)
+		.filter(|i| {
+			let ends = !closed && (*i == 0 || *i == n - 1);
+			let (before, point, after) = (points[(i + n - 1) % n], points[*i], points[(i + 1) % n]);
+			ends || !(before.0 == point.0 && point.0 == after.0 || before.1 == point.1 && point.1 == after.1)
+		})
+		.map(|i| points[i])
+		.collect()
+}
+
+/// Writes the collapsed cells of the field as an SVG image, filling cells
+/// with their color and drawing the connections of tiles as strokes
+/// `style.line_width` wide.
+pub fn write_svg<T: Tile + Vector>(field: &Field<T>, style: &Style, mut out: impl Write) -> std::io::Result<()> {
+	let size = style.cell_size;
+	let (width, height) = (field.width * size, render::rows(field) * size);
+	let half = size as f64 / 2.0;
+
+	writeln!(out, r#"<svg xmlns="http://www.w3.org/2000/svg" width="{0}" height="{1}" viewBox="0 0 {0} {1}">"#, width, height)?;
+	writeln!(out, r#"<rect width="{}" height="{}" {}/>"#, width, height, paint("fill", style.background))?;
+
+	writeln!(out, r#"<g shape-rendering="crispEdges">"#)?;
+	for (index, domain) in field.domains.iter().enumerate() {
+		let Domain::Collapsed(tile) = domain else {
+			continue;
+		};
+		if let Some(color) = field.tiles[*tile].fill() {
+			let (x, y) = render::cell(field, index);
+			let (x, y) = (x * size, y * size);
+			writeln!(out, r#"<rect x="{}" y="{}" width="{}" height="{}" {}/>"#, x, y, size, size, paint("fill", color))?;
+		}
+	}
+	writeln!(out, "</g>")?;
+
+	writeln!(
+		out,
+		r#"<g fill="none" {} stroke-width="{}" stroke-linecap="square" stroke-linejoin="miter">"#,
+		paint("stroke", style.foreground),
+		style.line_width,
+	)?;
+	for (points, closed) in lines(field) {
+		let points: Vec<String> = points.iter().map(|(x, y)| format!("{},{}", *x as f64 * half, *y as f64 * half)).collect();
+		let element = match closed {
+			true => "polygon",
+			false => "polyline",
+		};
+		writeln!(out, r#"<{} points="{}"/>"#, element, points.join(" "))?;
+	}
+	writeln!(out, "</g>")?;
+
+	writeln!(out, "</svg>")
+}
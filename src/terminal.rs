@@ -0,0 +1,128 @@
+use std::{fmt::Display, io::{Stderr, Write}, thread, time::Duration};
+
+use crate::{solver::Event, Domain, Field, Tile};
+
+const COLLAPSED: &str = "\x1b[30;42m";
+const TOUCHED: &str = "\x1b[44m";
+const CONTRADICTION: &str = "\x1b[97;41m";
+const RESET: &str = "\x1b[0m";
+
+/// Number of characters of `text` that take up room on a terminal, leaving
+/// out ANSI escape sequences.
+fn visible_width(text: &str) -> usize {
+	let mut width = 0;
+	let mut escape = false;
+	for c in text.chars() {
+		match (escape, c) {
+			(false, '\x1b') => escape = true,
+			(false, _) => width += 1,
+			(true, c) if c.is_ascii_alphabetic() => escape = false,
+			(true, _) => {},
+		}
+	}
+
+	width
+}
+
+/// Draws the steps of a generation to stderr, each one over the last.
+///
+/// The cell collapsed in the step is green, the cells changed by propagation
+/// or backtracking are blue and cells that ran out of tiles are red. Cells
+/// that are not collapsed show how many tiles they have left if it fits.
+pub struct Animation {
+	delay: Duration,
+	out: Stderr,
+	/// Lines written by the last step, to move back up over.
+	lines: usize,
+}
+impl Animation {
+	pub fn new(delay: Duration) -> Self {
+		Self {
+			delay,
+			out: std::io::stderr(),
+			lines: 0,
+		}
+	}
+	/// Redraws the field as it is after a step with the given events and
+	/// waits for the delay.
+	pub fn show<T: Tile + Display>(&mut self, field: &Field<T>, events: &[Event]) {
+		let glyphs: Vec<String> = field.tiles.iter().map(ToString::to_string).collect();
+		let width = glyphs.iter().map(|glyph| visible_width(glyph)).max().unwrap_or(1).max(1);
+
+		let mut frame = String::new();
+		if self.lines > 0 {
+			frame += &format!("\x1b[{}A\r", self.lines);
+		} else {
+			// hide the cursor
+			frame += "\x1b[?25l";
+		}
+
+		let mut highlights = vec![""; field.domains.len()];
+		let mut status = vec![];
+		let mut removed = 0;
+		let position = |index: usize| match field.position(index) {
+			(x, y, _) if field.depth == 1 => format!("({}, {})", x, y),
+			(x, y, z) => format!("({}, {}, {})", x, y, z),
+		};
+		for event in events {
+			match event {
+				Event::Observed { index, tile } => {
+					highlights[*index] = COLLAPSED;
+					status.push(format!("{} at {}", field.tiles[*tile].name(), position(*index)));
+				},
+				Event::Removed { index, tiles } => {
+					if highlights[*index].is_empty() {
+						highlights[*index] = TOUCHED;
+					}
+					removed += tiles.len();
+				},
+				Event::Contradiction { index } => {
+					highlights[*index] = CONTRADICTION;
+					status.push(format!("contradiction at {}", position(*index)));
+				},
+				Event::Backtracked { index, tile } => {
+					highlights[*index] = TOUCHED;
+					status.push(format!("banned {} at {}", field.tiles[*tile].name(), position(*index)));
+				},
+			}
+		}
+		status.push(format!("{} candidates removed", removed));
+
+		for (index, domain) in field.domains.iter().enumerate() {
+			let highlight = highlights[index];
+			let cell = match domain {
+				Domain::Collapsed(tile) => glyphs[*tile].clone(),
+				Domain::Superposition(candidates) => match candidates.len().to_string() {
+					count if count.len() <= width => format!("{:^1$}", count, width),
+					_ => " ".repeat(width),
+				},
+				Domain::Invalid => format!("{:^1$}", "!", width),
+			};
+			frame += &format!("{}{}{}", highlight, cell, RESET);
+
+			let (x, y, z) = field.position(index);
+			if x == field.width - 1 {
+				frame += "\n";
+			}
+			// an empty line between levels
+			if x == field.width - 1 && y == field.height - 1 && z + 1 < field.depth {
+				frame += "\n";
+			}
+		}
+
+		let collapsed = field.domains.iter().filter(|domain| matches!(domain, Domain::Collapsed(_))).count();
+		frame += &format!("\x1b[2K{} of {} collapsed, {}", collapsed, field.domains.len(), status.join(", "));
+		frame += "\n";
+
+		self.lines = field.height * field.depth + field.depth;
+		// a broken terminal only costs the animation, not the map
+		let _ = self.out.write_all(frame.as_bytes()).and_then(|()| self.out.flush());
+		thread::sleep(self.delay);
+	}
+}
+impl Drop for Animation {
+	fn drop(&mut self) {
+		// show the cursor again
+		let _ = write!(self.out, "\x1b[?25h");
+	}
+}
@@ -0,0 +1,357 @@
+use std::{fmt::Display, path::Path};
+
+use serde::Deserialize;
+
+use crate::{state_set::MAX_STATES, Direction, Pipe, Tile};
+
+/// A tile as described in a tileset file.
+///
+/// Two tiles may be placed next to each other when the sockets on their
+/// touching edges have the same name. Edges without a socket only match other
+/// edges without one. The `up` and `down` sockets join tiles of neighbouring
+/// levels in 3D fields.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TileDef {
+	pub name: String,
+	pub glyph: String,
+	#[serde(default = "default_weight")]
+	pub weight: usize,
+	#[serde(default)]
+	pub left: String,
+	#[serde(default)]
+	pub right: String,
+	#[serde(default)]
+	pub top: String,
+	#[serde(default)]
+	pub bottom: String,
+	#[serde(default)]
+	pub up: String,
+	#[serde(default)]
+	pub down: String,
+	#[serde(default)]
+	pub symmetry: Symmetry,
+}
+fn default_weight() -> usize {
+	1
+}
+impl TileDef {
+	fn socket(&self, direction: Direction) -> &str {
+		use Direction::*;
+
+		match direction {
+			Left => &self.left,
+			Right => &self.right,
+			Top => &self.top,
+			Bottom => &self.bottom,
+			Up => &self.up,
+			Down => &self.down,
+		}
+	}
+	/// The tile turned a quarter clockwise.
+	fn rotated(&self) -> Self {
+		Self {
+			glyph: transform_glyph(self.glyph.chars(), rotate),
+			top: self.left.clone(),
+			right: self.top.clone(),
+			bottom: self.right.clone(),
+			left: self.bottom.clone(),
+			symmetry: Symmetry::X,
+			..self.clone()
+		}
+	}
+	/// The tile mirrored left to right.
+	fn reflected(&self) -> Self {
+		Self {
+			glyph: transform_glyph(self.glyph.chars().rev(), reflect),
+			left: self.right.clone(),
+			right: self.left.clone(),
+			symmetry: Symmetry::X,
+			..self.clone()
+		}
+	}
+	/// The tile and every variant its symmetry asks for. Variants are named
+	/// after the tile with `_r90`, `_r180` and `_r270` for rotations and `_m`
+	/// for the mirrored tile and its rotations.
+	pub fn variants(&self) -> Vec<Self> {
+		use Symmetry::*;
+
+		let tile = Self {
+			symmetry: X,
+			..self.clone()
+		};
+		let rotations = |tile: Self, count: usize| {
+			let mut variants = vec![tile.clone()];
+			for angle in [90, 180, 270].into_iter().take(count - 1) {
+				let mut next = variants[variants.len() - 1].rotated();
+				next.name = format!("{}_r{}", tile.name, angle);
+				variants.push(next);
+			}
+
+			variants
+		};
+
+		match self.symmetry {
+			X => vec![tile],
+			I | Backslash => rotations(tile, 2),
+			L | T => rotations(tile, 4),
+			F => {
+				let mirrored = Self {
+					name: format!("{}_m", tile.name),
+					..tile.reflected()
+				};
+				let mut variants = rotations(tile, 4);
+				variants.extend(rotations(mirrored, 4));
+				variants
+			},
+		}
+	}
+}
+
+/// Which ways a tile can be turned and mirrored, named after letters with the
+/// same symmetry. Tilesets list one tile per class and get its other
+/// orientations generated.
+#[derive(Clone, Copy, Debug, Default, Deserialize)]
+pub enum Symmetry {
+	/// Looks the same however it is turned or mirrored, like a cross.
+	#[default]
+	X,
+	/// Has two orientations, like a straight pipe.
+	I,
+	/// Has two orientations, like a diagonal.
+	#[serde(rename = "\\")]
+	Backslash,
+	/// Has four orientations that are mirror images too, like a corner.
+	L,
+	/// Has four orientations, like a T junction.
+	T,
+	/// Has four orientations and four more mirrored, like the letter F.
+	F,
+}
+
+/// Characters that draw lines, in families with the same style. Each one is
+/// given with the sides it has lines towards, as `Direction` bits.
+const LINES: [&[(char, u8)]; 5] = [
+	&[
+		('╸', 0b0001), ('╺', 0b0010), ('╹', 0b0100), ('╻', 0b1000),
+		('━', 0b0011), ('┃', 0b1100), ('┛', 0b0101), ('┓', 0b1001), ('┗', 0b0110), ('┏', 0b1010),
+		('┫', 0b1101), ('┣', 0b1110), ('┻', 0b0111), ('┳', 0b1011), ('╋', 0b1111),
+	],
+	&[
+		('╴', 0b0001), ('╶', 0b0010), ('╵', 0b0100), ('╷', 0b1000),
+		('─', 0b0011), ('│', 0b1100), ('┘', 0b0101), ('┐', 0b1001), ('└', 0b0110), ('┌', 0b1010),
+		('┤', 0b1101), ('├', 0b1110), ('┴', 0b0111), ('┬', 0b1011), ('┼', 0b1111),
+	],
+	&[
+		('═', 0b0011), ('║', 0b1100), ('╝', 0b0101), ('╗', 0b1001), ('╚', 0b0110), ('╔', 0b1010),
+		('╣', 0b1101), ('╠', 0b1110), ('╩', 0b0111), ('╦', 0b1011), ('╬', 0b1111),
+	],
+	&[('←', 0b0001), ('→', 0b0010), ('↑', 0b0100), ('↓', 0b1000)],
+	&[('-', 0b0011), ('|', 0b1100), ('+', 0b1111)],
+];
+
+/// Sides turned a quarter clockwise: left becomes top, top right, right
+/// bottom and bottom left.
+fn rotate(sides: u8) -> u8 {
+	(sides & 0b0001) << 2 | (sides & 0b0100) >> 1 | (sides & 0b0010) << 2 | (sides & 0b1000) >> 3
+}
+/// Sides mirrored left to right.
+fn reflect(sides: u8) -> u8 {
+	(sides & 0b0001) << 1 | (sides & 0b0010) >> 1 | (sides & 0b1100)
+}
+
+/// The family and sides of a line drawing character.
+fn lines(c: char) -> Option<(&'static [(char, u8)], u8)> {
+	LINES
+		.iter()
+		.find_map(|family| family.iter().find(|(line, _)| *line == c).map(|(_, sides)| (*family, *sides)))
+}
+
+/// Moves the lines of every character of the glyph to the sides `transform`
+/// gives. Slashes swap, since turning and mirroring both flip them.
+///
+/// Pipes are often drawn a few characters wide, like `━┓ `, with horizontal
+/// arms on both sides of the character in the middle. Such arms are redrawn
+/// to match the transformed middle.
+fn transform_glyph(glyph: impl Iterator<Item = char>, transform: fn(u8) -> u8) -> String {
+	const LEFT: u8 = 1 << Direction::Left as u8;
+	const RIGHT: u8 = 1 << Direction::Right as u8;
+
+	let glyph: Vec<char> = glyph.collect();
+	let mut transformed: Vec<char> = glyph
+		.iter()
+		.map(|c| match (c, lines(*c)) {
+			('/', _) => '\\',
+			('\\', _) => '/',
+			(_, Some((family, sides))) => family
+				.iter()
+				.find(|(_, other)| *other == transform(sides))
+				.map_or(*c, |(line, _)| *line),
+			(_, None) => *c,
+		})
+		.collect();
+
+	let is_arm = |c: char| c == ' ' || lines(c).is_some_and(|(_, sides)| sides == LEFT | RIGHT);
+	if glyph.len() >= 3 {
+		let n = glyph.len();
+		for (arm, middle, side) in [(0, 1, LEFT), (n - 1, n - 2, RIGHT)] {
+			if !is_arm(glyph[arm]) {
+				continue;
+			}
+			if let Some((family, sides)) = lines(transformed[middle]) {
+				let horizontal = family.iter().find(|(_, other)| *other == LEFT | RIGHT);
+				transformed[arm] = match (sides & side != 0, horizontal) {
+					(true, Some((line, _))) => *line,
+					_ => ' ',
+				};
+			}
+		}
+	}
+
+	transformed.into_iter().collect()
+}
+impl Tile for TileDef {
+	fn name(&self) -> String {
+		self.name.clone()
+	}
+	fn weight(&self) -> usize {
+		self.weight
+	}
+	fn fits(&self, other: &Self, direction: Direction) -> bool {
+		self.socket(direction) == other.socket(direction.opposite())
+	}
+}
+impl Pipe for TileDef {
+	/// Edges with a socket count as connected.
+	fn connects(&self, direction: Direction) -> bool {
+		!self.socket(direction).is_empty()
+	}
+}
+impl Display for TileDef {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(f, "{}", self.glyph)
+	}
+}
+
+#[derive(Debug)]
+pub enum TilesetError {
+	Io(std::io::Error),
+	Parse(toml::de::Error),
+	Empty,
+	TooManyTiles(usize),
+	DuplicateName(String),
+	ZeroWeight(String),
+}
+impl Display for TilesetError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		use TilesetError::*;
+
+		match self {
+			Io(e) => write!(f, "Could not read tileset: {}", e),
+			Parse(e) => write!(f, "Could not parse tileset: {}", e),
+			Empty => write!(f, "Tileset has no tiles"),
+			TooManyTiles(count) => write!(f, "Tileset has {} tiles, at most {} are supported", count, MAX_STATES),
+			DuplicateName(name) => write!(f, "Tile {} is defined more than once", name),
+			ZeroWeight(name) => write!(f, "Tile {} has a weight of 0", name),
+		}
+	}
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Tileset {
+	#[serde(rename = "tile")]
+	pub tiles: Vec<TileDef>,
+}
+impl Tileset {
+	/// Reads a tileset from a TOML file with one `[[tile]]` table per tile,
+	/// adding the variants of tiles with a symmetry.
+	pub fn load(path: impl AsRef<Path>) -> Result<Self, TilesetError> {
+		let text = std::fs::read_to_string(path).map_err(TilesetError::Io)?;
+		let mut tileset: Tileset = toml::from_str(&text).map_err(TilesetError::Parse)?;
+		tileset.tiles = tileset.tiles.iter().flat_map(TileDef::variants).collect();
+		tileset.validate()?;
+
+		Ok(tileset)
+	}
+	fn validate(&self) -> Result<(), TilesetError> {
+		if self.tiles.is_empty() {
+			return Err(TilesetError::Empty);
+		}
+		if self.tiles.len() > MAX_STATES {
+			return Err(TilesetError::TooManyTiles(self.tiles.len()));
+		}
+		for (i, tile) in self.tiles.iter().enumerate() {
+			if tile.weight == 0 {
+				return Err(TilesetError::ZeroWeight(tile.name.clone()));
+			}
+			if self.tiles[..i].iter().any(|other| other.name == tile.name) {
+				return Err(TilesetError::DuplicateName(tile.name.clone()));
+			}
+		}
+
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn corner(symmetry: Symmetry) -> TileDef {
+		TileDef {
+			name: "corner".to_string(),
+			glyph: "━┓ ".to_string(),
+			weight: 1,
+			left: "pipe".to_string(),
+			right: String::new(),
+			top: String::new(),
+			bottom: "pipe".to_string(),
+			up: String::new(),
+			down: String::new(),
+			symmetry,
+		}
+	}
+
+	#[test]
+	fn rotate_and_reflect_sides() {
+		let (left, right, top, bottom) = (0b0001, 0b0010, 0b0100, 0b1000);
+		assert_eq!(rotate(left), top);
+		assert_eq!(rotate(top), right);
+		assert_eq!(rotate(right), bottom);
+		assert_eq!(rotate(bottom), left);
+		assert_eq!(reflect(left | top), right | top);
+		for sides in 0..16 {
+			assert_eq!(rotate(rotate(rotate(rotate(sides)))), sides);
+			assert_eq!(reflect(reflect(sides)), sides);
+		}
+	}
+
+	#[test]
+	fn transforms_glyphs() {
+		assert_eq!(transform_glyph("━┓ ".chars(), rotate), "━┛ ");
+		assert_eq!(transform_glyph("━┓ ".chars().rev(), reflect), " ┏━");
+		assert_eq!(transform_glyph("━━━".chars(), rotate), " ┃ ");
+		assert_eq!(transform_glyph("─┬─".chars(), rotate), "─┤ ");
+		assert_eq!(transform_glyph("/".chars(), rotate), "\\");
+		assert_eq!(transform_glyph("a→".chars(), rotate), "a↓");
+	}
+
+	#[test]
+	fn names_variants() {
+		let names = |symmetry| corner(symmetry).variants().iter().map(Tile::name).collect::<Vec<_>>();
+		assert_eq!(names(Symmetry::X), ["corner"]);
+		assert_eq!(names(Symmetry::I), ["corner", "corner_r90"]);
+		assert_eq!(names(Symmetry::L), ["corner", "corner_r90", "corner_r180", "corner_r270"]);
+		assert_eq!(
+			names(Symmetry::F),
+			["corner", "corner_r90", "corner_r180", "corner_r270", "corner_m", "corner_m_r90", "corner_m_r180", "corner_m_r270"]
+		);
+
+		let variants = corner(Symmetry::L).variants();
+		assert_eq!(variants[1].glyph, "━┛ ");
+		assert_eq!((variants[1].left.as_str(), variants[1].top.as_str()), ("pipe", "pipe"));
+		assert!(variants[1].bottom.is_empty() && variants[1].right.is_empty());
+		assert!(variants.iter().all(|variant| matches!(variant.symmetry, Symmetry::X)));
+	}
+}